@@ -15,20 +15,27 @@ import androidx.lifecycle.viewModelScope
 import com.anvlkv.redsiren.core.typegen.Activity
 import com.anvlkv.redsiren.core.typegen.AnimateOperation
 import com.anvlkv.redsiren.core.typegen.AnimateOperationOutput
+import com.anvlkv.redsiren.core.typegen.CaptureOutput
 import com.anvlkv.redsiren.core.typegen.Effect
 import com.anvlkv.redsiren.core.typegen.Event
 import com.anvlkv.redsiren.core.typegen.KeyValueOperation
 import com.anvlkv.redsiren.core.typegen.KeyValueOutput
+import com.anvlkv.redsiren.core.typegen.MidiOperation
 import com.anvlkv.redsiren.core.typegen.NavigateOperation
 import com.anvlkv.redsiren.core.typegen.PlayOperation
+import com.anvlkv.redsiren.core.typegen.PlayError
 import com.anvlkv.redsiren.core.typegen.PlayOperationOutput
 import com.anvlkv.redsiren.core.typegen.Request
 import com.anvlkv.redsiren.core.typegen.Requests
+import com.anvlkv.redsiren.core.typegen.ShareOperation
 import com.anvlkv.redsiren.core.typegen.ViewModel
+import com.anvlkv.redsiren.ffirs.AuCaptureReceiver
 import com.anvlkv.redsiren.ffirs.AuCoreBridge
 import com.anvlkv.redsiren.ffirs.AuReceiver
+import com.anvlkv.redsiren.ffirs.auCapture
 import com.anvlkv.redsiren.ffirs.auNew
 import com.anvlkv.redsiren.ffirs.auReceive
+import com.anvlkv.redsiren.ffirs.auReceiveCapture
 import com.anvlkv.redsiren.ffirs.auRequest
 import com.anvlkv.redsiren.ffirs.handleResponse
 import com.anvlkv.redsiren.ffirs.logInit
@@ -58,6 +65,7 @@ open class Core : androidx.lifecycle.ViewModel() {
     private val httpClient = HttpClient(CIO)
 
     var onRequestPermissions: (() -> CompletableDeferred<Boolean>)? = null
+    var onSaveFile: ((String, ByteArray) -> Unit)? = null
 
     init {
         viewModelScope.launch {
@@ -180,6 +188,23 @@ open class Core : androidx.lifecycle.ViewModel() {
                     }
                 }
             }
+
+            is Effect.Midi -> {
+                when (val op = effect.value) {
+                    is MidiOperation.Export -> {
+                        onSaveFile?.invoke("red-siren.mid", op.value.toByteArray())
+                    }
+                    else -> {}
+                }
+            }
+
+            is Effect.Share -> {
+                when (val op = effect.value) {
+                    is ShareOperation.Recording -> {
+                        onSaveFile?.invoke("red-siren.wav", op.value.toByteArray())
+                    }
+                }
+            }
         }
     }
 
@@ -217,11 +242,20 @@ open class Core : androidx.lifecycle.ViewModel() {
                     val deferred = requestPermissions.invoke()
                     grant = deferred.await()
                 }
-                onData(PlayOperationOutput.Permission(grant).bincodeSerialize()).join()
+                val output = if (grant) PlayOperationOutput.Success() else PlayOperationOutput.Failure(PlayError.PermissionDenied())
+                onData(output.bincodeSerialize()).join()
             }
 
             is PlayOperation.InstallAU -> {
-                installAu()
+                capture()?.let { rec ->
+                    viewModelScope.launch {
+                        while (true) {
+                            val d = auReceiveCapture(rec) ?: break
+                            update(Event.Capture(CaptureOutput.bincodeDeserialize(d)))
+                        }
+                        Log.i("redsiren::android", "capture stream ended")
+                    }
+                }
                 forward(value)?.let {rec ->
                     auReceive(rec)?.let {
                         onData(it).join()
@@ -245,14 +279,17 @@ open class Core : androidx.lifecycle.ViewModel() {
     private companion object {
         private var auBridge: AuCoreBridge? = null
 
-        fun installAu() {
-            auBridge = auNew()
+        // one bridge for the app's lifetime, reinstalling keeps the selected devices
+        fun bridge(): AuCoreBridge {
+            return auBridge ?: auNew().also { auBridge = it }
+        }
+
+        fun capture(): AuCaptureReceiver? {
+            return auCapture(bridge())
         }
 
         fun forward(op: PlayOperation): AuReceiver? {
-            return auBridge?.let {
-                return auRequest(it, op.bincodeSerialize())
-            }
+            return auRequest(bridge(), op.bincodeSerialize())
         }
     }
 }
@@ -44,6 +44,7 @@ import com.google.accompanist.permissions.isGranted
 import com.google.accompanist.permissions.rememberPermissionState
 import kotlinx.coroutines.CompletableDeferred
 import kotlinx.coroutines.launch
+import java.io.File
 import com.anvlkv.redsiren.core.typegen.Activity as CoreActivity
 
 
@@ -203,6 +204,12 @@ fun RedSiren(core: Core) {
     val context = LocalContext.current
     val cutouts = context.display?.cutout
 
+    core.onSaveFile = fun(name: String, data: ByteArray) {
+        val file = File(context.getExternalFilesDir(null), name)
+        file.writeBytes(data)
+        Log.i("redsiren::android", "saved ${file.path}")
+    }
+
     val safeAreas = remember {
         cutouts?.let {
             arrayOf(
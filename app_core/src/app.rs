@@ -10,8 +10,10 @@ use serde::{Deserialize, Serialize};
 use crate::{animate::Animate, geometry::Rect};
 pub use instrument::Instrument;
 pub use intro::Intro;
+pub use midi::Midi;
 pub use navigate::Navigate;
 pub use play::Play;
+pub use share::Share;
 pub use tuner::Tuner;
 
 use self::{
@@ -21,8 +23,10 @@ use self::{
 pub mod animate;
 pub mod instrument;
 pub mod intro;
+pub mod midi;
 pub mod navigate;
 pub mod play;
+pub mod share;
 pub mod tuner;
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
@@ -105,6 +109,8 @@ pub struct RedSirenCapabilities {
     pub navigate: Navigate<Event>,
     pub play: Play<Event>,
     pub animate: Animate<Event>,
+    pub midi: Midi<Event>,
+    pub share: Share<Event>,
 }
 
 impl From<&RedSirenCapabilities> for IntroCapabilities {
@@ -134,6 +140,8 @@ impl From<&RedSirenCapabilities> for InstrumentCapabilities {
             render: incoming.render.map_event(super::Event::InstrumentEvent),
             play: incoming.play.map_event(super::Event::InstrumentEvent),
             navigate: incoming.navigate.map_event(super::Event::InstrumentEvent),
+            midi: incoming.midi.map_event(super::Event::InstrumentEvent),
+            share: incoming.share.map_event(super::Event::InstrumentEvent),
         }
     }
 }
@@ -166,8 +174,12 @@ impl App for RedSiren {
                 if act == Activity::Play {
                     if !self.tuner.is_tuned(&model.tuner) {
                         self.update(Event::Menu(Activity::Tune), model, caps);
-                    } else if let Some(d) = model.tuner.tuning.as_ref() {
-                        model.instrument.tuning = d.clone();
+                    } else if let Some(d) = model.tuner.tuning.clone() {
+                        self.instrument.update(
+                            instrument::InstrumentEV::UpdateTuning(d),
+                            &mut model.instrument,
+                            &caps.into(),
+                        );
                     }
                 } else if act == Activity::Tune {
                     model.tuner.state = if model.instrument.setup_complete {
@@ -271,8 +283,18 @@ impl App for RedSiren {
                         if let Some(tuning) = model.tuner.tuning.clone() {
                             model.instrument.setup_complete =
                                 model.tuner.state >= tuner::State::SetupComplete;
-                            model.instrument.tuning = tuning;
-                            model.instrument.configured = false;
+                            self.instrument.update(
+                                instrument::InstrumentEV::UpdateTuning(tuning),
+                                &mut model.instrument,
+                                &caps.into(),
+                            );
+                            if let Some(floor) = model.tuner.noise_floor.clone() {
+                                self.instrument.update(
+                                    instrument::InstrumentEV::UpdateNoiseFloor(floor),
+                                    &mut model.instrument,
+                                    &caps.into(),
+                                );
+                            }
                             self.intro.update(
                                 intro::IntroEV::Menu(act),
                                 &mut model.intro,
@@ -348,6 +370,37 @@ impl App for RedSiren {
                         &caps.into(),
                     );
                 }
+                play::CaptureOutput::CapturePeaks(d) => {
+                    self.tuner
+                        .update(tuner::TunerEV::PeaksData(d), &mut model.tuner, &caps.into())
+                }
+                play::CaptureOutput::CaptureRecording(d) => self.instrument.update(
+                    instrument::InstrumentEV::RecordingData(d),
+                    &mut model.instrument,
+                    &caps.into(),
+                ),
+                play::CaptureOutput::CaptureNoiseFloor(d) => self.tuner.update(
+                    tuner::TunerEV::NoiseFloorData(d),
+                    &mut model.tuner,
+                    &caps.into(),
+                ),
+                play::CaptureOutput::CaptureLatency(d) => self.instrument.update(
+                    instrument::InstrumentEV::LatencyData(d),
+                    &mut model.instrument,
+                    &caps.into(),
+                ),
+                play::CaptureOutput::CaptureInputLevel(d) => match model.activity {
+                    Activity::Tune => self.tuner.update(
+                        tuner::TunerEV::InputLevel(d),
+                        &mut model.tuner,
+                        &caps.into(),
+                    ),
+                    _ => self.instrument.update(
+                        instrument::InstrumentEV::InputLevel(d),
+                        &mut model.instrument,
+                        &caps.into(),
+                    ),
+                },
 
             },
             Event::IntroEvent(event) => self.intro.update(event, &mut model.intro, &caps.into()),
@@ -366,4 +419,95 @@ impl App for RedSiren {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::{
+        instrument::{InstrumentEV, Node, NodeEvent},
+        midi::{self, MidiMessage, MidiOperation},
+        play::PlayOperation,
+        share::ShareOperation,
+    };
+    use crux_core::testing::AppTester;
+
+    #[test]
+    fn hands_the_finished_recording_to_the_shell() {
+        let app = AppTester::<RedSiren, Effect>::default();
+        let mut model = Model::default();
+        model.instrument.recording = true;
+
+        let update = app.update(
+            Event::InstrumentEvent(InstrumentEV::StopRecording),
+            &mut model,
+        );
+        assert!(update.effects.iter().any(|effect| matches!(
+            effect,
+            Effect::Play(request) if request.operation == PlayOperation::StopRecording
+        )));
+
+        let wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        let update = app.update(
+            Event::Capture(play::CaptureOutput::CaptureRecording(wav.clone())),
+            &mut model,
+        );
+        let shared = update
+            .effects
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Share(request) => Some(request.operation),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(shared, vec![ShareOperation::Recording(wav)]);
+    }
+
+    #[test]
+    fn exports_the_midi_recording_as_smf() {
+        let app = AppTester::<RedSiren, Effect>::default();
+        let mut model = Model::default();
+        let node = model._world.lock().expect("world lock").spawn((Node {
+            freq: (220.0, 440.0),
+            f_n: 1,
+            pan: 0.0,
+            triggered: 0.0,
+            voice: Default::default(),
+        },));
+        model.instrument.nodes.push(node);
+
+        for ev in [
+            InstrumentEV::StartMidiRecording,
+            InstrumentEV::NodeEvent(NodeEvent::NodeOn {
+                f_n: 1,
+                velocity: 0.5,
+            }),
+            InstrumentEV::NodeEvent(NodeEvent::NodeOff { f_n: 1 }),
+        ] {
+            app.update(Event::InstrumentEvent(ev), &mut model);
+        }
+        let update = app.update(
+            Event::InstrumentEvent(InstrumentEV::StopMidiRecording),
+            &mut model,
+        );
+
+        let exported = update
+            .effects
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Midi(request) => match request.operation {
+                    MidiOperation::Export(smf) => Some(smf),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(&exported[0][..4], b"MThd");
+
+        let messages = midi::smf::read(&exported[0]).expect("valid smf");
+        assert!(messages
+            .iter()
+            .any(|(_, m)| matches!(m, MidiMessage::NoteOn { .. })));
+        assert!(messages
+            .iter()
+            .any(|(_, m)| matches!(m, MidiMessage::NoteOff { .. })));
+    }
+}
@@ -1,7 +1,4 @@
-use std::{
-    cmp::Ordering,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
 use crux_core::render::Render;
 use crux_core::App;
@@ -11,15 +8,33 @@ use mint::Point2;
 use serde::{Deserialize, Serialize};
 
 pub use config::Config;
+pub use envelope::{EnvelopeParams, NodeEvent};
 pub use layout::{Layout, LayoutRoot};
 use node::spawn_all_nodes;
-pub use node::Node;
+pub use node::{Node, Voice};
+
+use crate::{
+    midi::{Midi, MidiMessage, Performance, SmfRecorder},
+    play::{
+        Devices, DevicesVM, FxChain, InputLevel, InputMeter, Latency, Meter, Play, PlayError,
+        PlayOperation, PlayOperationOutput, Transport,
+    },
+    share::Share,
+    tuner::TuningValue,
+    Navigate,
+};
 
-use crate::{play::Play, tuner::TuningValue, Navigate};
+use self::{
+    envelope::Envelope,
+    keyboard::{pressure_q, Button, Track},
+    string::OutboundString,
+};
 
-use self::string::OutboundString;
+pub use self::delay::{Delay, Snoop};
 
 pub mod config;
+pub mod delay;
+pub mod envelope;
 pub mod keyboard;
 pub mod layout;
 pub mod node;
@@ -42,7 +57,28 @@ pub struct Model {
     pub setup_complete: bool,
     pub configured: bool,
     pub tuning: Vec<TuningValue>,
+    /// ambient noise floor of each node from the tuner's calibration
+    pub noise_floor: Vec<(usize, f32)>,
     pub snooped: Vec<f32>,
+    pub input_meter: InputMeter,
+    pub recording: bool,
+    pub effects: FxChain,
+    pub node_events: Vec<NodeEvent>,
+    /// seconds, as of the latest snoop request
+    pub clock: f64,
+    pub midi_out: bool,
+    pub performance: Performance,
+    pub midi_recorder: Option<SmfRecorder>,
+    /// a MIDI file is loaded to play on the nodes
+    pub midi_loaded: bool,
+    pub midi_playing: bool,
+    /// measured round trip from the output back to the input
+    pub latency: Option<Latency>,
+    pub measuring_latency: bool,
+    pub snoop_delay: Delay<Snoop>,
+    /// why playback last failed, until recovered
+    pub error: Option<PlayError>,
+    pub devices: Devices,
 }
 
 impl Model {
@@ -62,6 +98,21 @@ pub struct InstrumentVM {
     pub playing: bool,
     pub layout: Layout,
     pub data_out: Vec<Point2<f64>>,
+    pub input_meter: Meter,
+    pub recording: bool,
+    pub effects: FxChain,
+    /// transitions from the latest snoops
+    pub node_events: Vec<NodeEvent>,
+    /// envelope level history by `f_n`
+    pub levels: Vec<(usize, Vec<f32>)>,
+    pub midi_out: bool,
+    pub midi_recording: bool,
+    pub midi_loaded: bool,
+    pub midi_playing: bool,
+    pub latency: Option<Latency>,
+    pub measuring_latency: bool,
+    pub error: Option<PlayError>,
+    pub devices: DevicesVM,
 }
 
 impl Eq for InstrumentVM {}
@@ -69,7 +120,7 @@ impl Eq for InstrumentVM {}
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum PlaybackEV {
     Play(bool),
-    Error,
+    Error(PlayError),
 }
 
 impl Eq for PlaybackEV {}
@@ -79,14 +130,55 @@ pub enum InstrumentEV {
     None,
     CreateWithConfig(Config),
     Playback(PlaybackEV),
-    PlayOpPermission(bool),
-    PlayOpInstall(bool),
-    PlayOpConfigure(bool),
-    PlayOpPlay(bool),
-    PlayOpPause(bool),
+    PlayOpPermission(PlayOperationOutput),
+    PlayOpInstall(PlayOperationOutput),
+    PlayOpConfigure(PlayOperationOutput),
+    PlayOpPlay(PlayOperationOutput),
+    PlayOpPause(PlayOperationOutput),
     SnoopData(Vec<f32>),
     NodeSnoopData(Vec<(usize, Vec<f32>)>),
-    RequestSnoops,
+    InputLevel(InputLevel),
+    NodeEvent(NodeEvent),
+    SetEnvelope(Option<usize>, EnvelopeParams),
+    /// shell timestamp in milliseconds
+    RequestSnoops(f64),
+    UpdateTuning(Vec<TuningValue>),
+    UpdateNoiseFloor(Vec<(usize, f32)>),
+    StartRecording(f64),
+    StopRecording,
+    PlayOpStartRecording(PlayOperationOutput),
+    PlayOpStopRecording(PlayOperationOutput),
+    /// WAV bytes of the finished recording
+    RecordingData(Vec<u8>),
+    SetVoice(Option<usize>, Voice),
+    SetPan(usize, f32),
+    /// pointer down at a position, with pointer id and pressure 0.0 to 1.0
+    ActivationXY((f64, f64), i32, f32),
+    MovementXY((f64, f64), i32, f32),
+    DeactivationXY(i32),
+    SetEffects(FxChain),
+    PlayOpEffects(PlayOperationOutput),
+    SetMidiOut(bool),
+    StartMidiRecording,
+    StopMidiRecording,
+    /// a Standard MIDI File to play on the nodes
+    LoadMidi(Vec<u8>),
+    PlayOpLoadMidi(PlayOperationOutput),
+    MidiTransport(Transport),
+    PlayOpMidiTransport(Transport, PlayOperationOutput),
+    MeasureLatency,
+    PlayOpMeasureLatency(PlayOperationOutput),
+    LatencyData(Option<Latency>),
+    /// clears a playback error and sets up again
+    Recover,
+    QueryDevices,
+    PlayOpInputDevices(PlayOperationOutput),
+    PlayOpOutputDevices(PlayOperationOutput),
+    /// input device by id, `None` for the system default
+    SelectInputDevice(Option<String>),
+    /// output device by id, `None` for the system default
+    SelectOutputDevice(Option<String>),
+    PlayOpSelectDevices(PlayOperationOutput),
 }
 
 impl Eq for InstrumentEV {}
@@ -98,6 +190,8 @@ pub struct InstrumentCapabilities {
     pub render: Render<InstrumentEV>,
     pub play: Play<InstrumentEV>,
     pub navigate: Navigate<InstrumentEV>,
+    pub midi: Midi<InstrumentEV>,
+    pub share: Share<InstrumentEV>,
 }
 
 impl App for Instrument {
@@ -130,7 +224,7 @@ impl App for Instrument {
                     _ = model.outbound.insert(outbound);
                     _ = model.keyboard.insert(keyboard);
 
-                    model.nodes = spawn_all_nodes(&mut world);
+                    model.nodes = spawn_all_nodes(&mut world, &config);
                 }
 
                 if model.playing {
@@ -149,19 +243,186 @@ impl App for Instrument {
 
                 caps.render.render();
             }
-            InstrumentEV::PlayOpPermission(grant) => {
-                if grant {
-                    caps.play.install_au(InstrumentEV::PlayOpInstall)
+            InstrumentEV::PlayOpPermission(grant) => match grant.error() {
+                None => caps.play.install_au(InstrumentEV::PlayOpInstall),
+                Some(e) => self.update(InstrumentEV::Playback(PlaybackEV::Error(e)), model, caps),
+            },
+            InstrumentEV::RequestSnoops(ts) => {
+                model.clock = ts / 1000.0;
+                for snoop in model.snoop_delay.ready(model.clock) {
+                    self.show_snoop(model, snoop, caps);
+                }
+                caps.play.query_snoops()
+            }
+            InstrumentEV::UpdateTuning(tuning) => {
+                if model.configured && tuning.len() == model.tuning.len() {
+                    for (old, new) in model.tuning.iter().zip(tuning.iter()) {
+                        if old != new {
+                            caps.play.tune_node(*new);
+                        }
+                    }
                 } else {
-                    caps.navigate.to(crate::Activity::Intro)
+                    model.configured = false;
+                }
+                model.tuning = tuning;
+            }
+            InstrumentEV::UpdateNoiseFloor(floor) => {
+                if model.configured {
+                    caps.play.noise_floor(floor.as_slice());
+                }
+                model.noise_floor = floor;
+            }
+            InstrumentEV::SetVoice(f_n, voice) => {
+                {
+                    let mut world = model.world.lock().expect("world lock");
+                    for (_, node) in world
+                        .query_mut::<&mut Node>()
+                        .into_iter()
+                        .filter(|(_, node)| f_n.map_or(true, |f_n| node.f_n == f_n))
+                    {
+                        node.voice = voice;
+                    }
+                }
+
+                if model.configured {
+                    let nodes = self.get_nodes(model);
+                    caps.play.configure(
+                        &model.config,
+                        nodes.as_slice(),
+                        &model.tuning.as_slice(),
+                        InstrumentEV::PlayOpConfigure,
+                    );
+                }
+
+                caps.render.render();
+            }
+            InstrumentEV::SetPan(f_n, pan) => {
+                let pan = pan.clamp(-1.0, 1.0);
+                {
+                    let mut world = model.world.lock().expect("world lock");
+                    for (_, node) in world
+                        .query_mut::<&mut Node>()
+                        .into_iter()
+                        .filter(|(_, node)| node.f_n == f_n)
+                    {
+                        node.pan = pan;
+                    }
+                }
+
+                if model.configured {
+                    caps.play.update_node(PlayOperation::NodePan(f_n, pan));
                 }
+
+                caps.render.render();
+            }
+            InstrumentEV::ActivationXY((x, y), id, pressure) => {
+                let pt = Point2 { x, y };
+                let hit = {
+                    let world = model.world.lock().expect("world lock");
+                    let hit = world
+                        .query::<&Button>()
+                        .iter()
+                        .find(|(_, b)| {
+                            b.finger.is_none()
+                                && (b.rect.contains(pt)
+                                    || world
+                                        .get::<&Track>(b.track)
+                                        .map_or(false, |t| t.rect.contains(pt)))
+                        })
+                        .map(|(e, _)| e);
+
+                    if let Some(e) = hit {
+                        world.get::<&mut Button>(e).expect("button for entity").finger = Some(id);
+                    }
+
+                    hit
+                };
+
+                if hit.is_some() {
+                    self.express(model, id, pt, pressure, caps);
+                    caps.render.render();
+                }
+            }
+            InstrumentEV::MovementXY((x, y), id, pressure) => {
+                self.express(model, id, Point2 { x, y }, pressure, caps);
             }
-            InstrumentEV::RequestSnoops => caps.play.query_snoops(),
-            InstrumentEV::PlayOpInstall(success) => {
-                if !success {
-                    self.update(InstrumentEV::Playback(PlaybackEV::Error), model, caps)
+            InstrumentEV::DeactivationXY(id) => {
+                let released = {
+                    let world = model.world.lock().expect("world lock");
+                    let mut query = world.query::<&mut Button>();
+                    query
+                        .iter()
+                        .find(|(_, b)| b.finger == Some(id))
+                        .map(|(_, b)| {
+                            b.finger = None;
+                            let track = world.get::<&Track>(b.track).expect("track for button");
+                            (b.f_n, track.freq.0)
+                        })
+                };
+
+                if let Some((f_n, freq)) = released {
+                    if model.playing && model.configured {
+                        caps.play.update_node(PlayOperation::NodeFreq(f_n, freq));
+                        caps.play.update_node(PlayOperation::NodeQ(
+                            f_n,
+                            pressure_q(0.0, model.config.n_buttons),
+                        ));
+
+                        let bend = model.performance.node_freq(f_n, freq);
+                        self.send_midi(model, bend, caps);
+                    }
+                    caps.render.render();
+                }
+            }
+            InstrumentEV::SetEffects(chain) => {
+                if model.setup_complete {
+                    caps.play.effects(&chain, InstrumentEV::PlayOpEffects);
+                }
+                model.effects = chain;
+                caps.render.render();
+            }
+            InstrumentEV::PlayOpEffects(done) => {
+                if let Some(e) = done.error() {
+                    log::error!("effects not applied: {e}");
+                }
+            }
+            InstrumentEV::StartRecording(max_s) => {
+                if model.playing && !model.recording {
+                    caps.play
+                        .start_recording(max_s, InstrumentEV::PlayOpStartRecording);
+                } else {
+                    log::warn!("not starting recording");
+                }
+            }
+            InstrumentEV::StopRecording => {
+                if model.recording {
+                    caps.play.stop_recording(InstrumentEV::PlayOpStopRecording);
+                }
+            }
+            InstrumentEV::PlayOpStartRecording(recording) => {
+                model.recording = recording.is_success();
+                caps.render.render();
+            }
+            InstrumentEV::PlayOpStopRecording(stopped) => {
+                if let Some(e) = stopped.error() {
+                    log::error!("recording failed: {e}");
+                }
+                model.recording = false;
+                caps.render.render();
+            }
+            InstrumentEV::RecordingData(wav) => {
+                log::info!("sharing {} bytes of recording", wav.len());
+                caps.share.recording(wav);
+            }
+            InstrumentEV::PlayOpInstall(done) => {
+                if let Some(e) = done.error() {
+                    self.update(InstrumentEV::Playback(PlaybackEV::Error(e)), model, caps)
                 } else {
                     model.setup_complete = true;
+                    if !model.effects.0.is_empty() {
+                        caps.play
+                            .effects(&model.effects, InstrumentEV::PlayOpEffects);
+                    }
                     let nodes = self.get_nodes(model);
                     caps.play.configure(
                         &model.config,
@@ -171,11 +432,14 @@ impl App for Instrument {
                     );
                 }
             }
-            InstrumentEV::PlayOpConfigure(success) => {
-                model.configured = success;
-                if !success {
-                    self.update(InstrumentEV::Playback(PlaybackEV::Error), model, caps)
+            InstrumentEV::PlayOpConfigure(done) => {
+                model.configured = done.is_success();
+                if let Some(e) = done.error() {
+                    self.update(InstrumentEV::Playback(PlaybackEV::Error(e)), model, caps)
                 } else {
+                    if !model.noise_floor.is_empty() {
+                        caps.play.noise_floor(model.noise_floor.as_slice());
+                    }
                     self.update(
                         InstrumentEV::Playback(PlaybackEV::Play(model.playing)),
                         model,
@@ -183,14 +447,14 @@ impl App for Instrument {
                     )
                 }
             }
-            InstrumentEV::PlayOpPause(success) => {
-                if !success {
-                    self.update(InstrumentEV::Playback(PlaybackEV::Error), model, caps)
+            InstrumentEV::PlayOpPause(done) => {
+                if let Some(e) = done.error() {
+                    self.update(InstrumentEV::Playback(PlaybackEV::Error(e)), model, caps)
                 }
             }
-            InstrumentEV::PlayOpPlay(success) => {
-                if !success {
-                    self.update(InstrumentEV::Playback(PlaybackEV::Error), model, caps)
+            InstrumentEV::PlayOpPlay(done) => {
+                if let Some(e) = done.error() {
+                    self.update(InstrumentEV::Playback(PlaybackEV::Error(e)), model, caps)
                 } else if !model.configured && model.playing {
                     let nodes = self.get_nodes(model);
                     caps.play.configure(
@@ -203,6 +467,16 @@ impl App for Instrument {
             }
             InstrumentEV::Playback(playback_ev) => match playback_ev {
                 PlaybackEV::Play(playing) => {
+                    if playing {
+                        model.error = None;
+                    }
+                    if !playing && model.recording {
+                        caps.play.stop_recording(InstrumentEV::PlayOpStopRecording);
+                    }
+                    if !playing {
+                        let messages = model.performance.all_off();
+                        self.send_midi(model, messages, caps);
+                    }
                     model.playing = playing;
                     model.snooped = vec![];
                     if !model.setup_complete {
@@ -214,50 +488,201 @@ impl App for Instrument {
                     }
                     caps.render.render();
                 }
-                PlaybackEV::Error => {
+                PlaybackEV::Error(e) => {
+                    log::error!("playback failed: {e}");
                     model.playing = false;
                     model.setup_complete = false;
+                    model.configured = false;
+                    _ = model.error.insert(e);
                     caps.render.render();
                 }
             },
-            InstrumentEV::SnoopData(d) => {
-                model.snooped = d;
-
-                let world = model.world.lock().expect("lock world");
-                let mut outbound = model
-                    .outbound
-                    .as_ref()
-                    .map(|e| world.get::<&mut OutboundString>(*e).ok())
-                    .flatten()
-                    .expect("get string");
-
-                outbound.update_data(model.snooped.clone(), &model.config);
+            InstrumentEV::SnoopData(d) => match model.latency {
+                Some(_) => model.snoop_delay.push(model.clock, Snoop::Output(d)),
+                None => self.show_snoop(model, Snoop::Output(d), caps),
+            },
+            InstrumentEV::InputLevel(level) => {
+                model.input_meter.push(level);
                 caps.render.render();
             }
-            InstrumentEV::NodeSnoopData(d) => {
-                let mut world = model.world.lock().expect("lock world");
-                for (f_n, d) in d {
-                    let d_max: f32 = *d
+            InstrumentEV::NodeSnoopData(d) => match model.latency {
+                Some(_) => model.snoop_delay.push(model.clock, Snoop::Nodes(d)),
+                None => self.show_snoop(model, Snoop::Nodes(d), caps),
+            },
+            InstrumentEV::MeasureLatency => {
+                if model.configured && model.playing && !model.measuring_latency {
+                    model.measuring_latency = true;
+                    caps.play.measure_latency(InstrumentEV::PlayOpMeasureLatency);
+                    caps.render.render();
+                } else {
+                    log::warn!("not playing to measure latency");
+                }
+            }
+            InstrumentEV::PlayOpMeasureLatency(measuring) => {
+                if let Some(e) = measuring.error() {
+                    log::error!("latency measurement not started: {e}");
+                    model.measuring_latency = false;
+                    caps.render.render();
+                }
+            }
+            InstrumentEV::Recover => {
+                if model.error.is_some() {
+                    self.update(InstrumentEV::Playback(PlaybackEV::Play(true)), model, caps);
+                }
+            }
+            InstrumentEV::QueryDevices => {
+                caps.play
+                    .query_input_devices(InstrumentEV::PlayOpInputDevices);
+                caps.play
+                    .query_output_devices(InstrumentEV::PlayOpOutputDevices);
+            }
+            InstrumentEV::PlayOpInputDevices(devices) => match devices {
+                PlayOperationOutput::Devices(devices) => {
+                    if model.devices.set_inputs(devices) {
+                        log::warn!("selected input is gone, using the default");
+                        caps.play.select_devices(
+                            &model.devices.selection,
+                            InstrumentEV::PlayOpSelectDevices,
+                        );
+                    }
+                    caps.render.render();
+                }
+                done => {
+                    if let Some(e) = done.error() {
+                        log::error!("input devices not listed: {e}");
+                    }
+                }
+            },
+            InstrumentEV::PlayOpOutputDevices(devices) => match devices {
+                PlayOperationOutput::Devices(devices) => {
+                    if model.devices.set_outputs(devices) {
+                        log::warn!("selected output is gone, using the default");
+                        caps.play.select_devices(
+                            &model.devices.selection,
+                            InstrumentEV::PlayOpSelectDevices,
+                        );
+                    }
+                    caps.render.render();
+                }
+                done => {
+                    if let Some(e) = done.error() {
+                        log::error!("output devices not listed: {e}");
+                    }
+                }
+            },
+            InstrumentEV::SelectInputDevice(id) => {
+                model.devices.selection.input = id;
+                caps.play
+                    .select_devices(&model.devices.selection, InstrumentEV::PlayOpSelectDevices);
+                caps.render.render();
+            }
+            InstrumentEV::SelectOutputDevice(id) => {
+                model.devices.selection.output = id;
+                caps.play
+                    .select_devices(&model.devices.selection, InstrumentEV::PlayOpSelectDevices);
+                caps.render.render();
+            }
+            InstrumentEV::PlayOpSelectDevices(selected) => {
+                if let Some(e) = selected.error() {
+                    // the listing is likely stale, refreshing it drops what's gone
+                    log::error!("devices not selected: {e}");
+                    self.update(InstrumentEV::QueryDevices, model, caps);
+                } else if model.setup_complete {
+                    // streams open on the selected devices when installed again
+                    model.setup_complete = false;
+                    model.configured = false;
+                    if model.playing {
+                        self.update(InstrumentEV::Playback(PlaybackEV::Play(true)), model, caps);
+                    }
+                }
+            }
+            InstrumentEV::LatencyData(latency) => {
+                model.measuring_latency = false;
+                match latency {
+                    Some(latency) => {
+                        log::info!("round trip latency: {}ms", latency.millis);
+                        model.snoop_delay = Delay::for_latency(&latency);
+                        _ = model.latency.insert(latency);
+                    }
+                    None => log::warn!("latency probe not heard"),
+                }
+                caps.render.render();
+            }
+            InstrumentEV::NodeEvent(ev) => {
+                log::trace!("{ev:?}");
+                model.node_events.push(ev);
+
+                let messages = match ev {
+                    NodeEvent::NodeOn { f_n, velocity } => self
+                        .get_nodes(model)
                         .iter()
-                        .max_by(|v, v1| {
-                            if v.abs() > v1.abs() {
-                                Ordering::Greater
-                            } else if v.abs() < v1.abs() {
-                                Ordering::Less
-                            } else {
-                                Ordering::Equal
-                            }
-                        })
-                        .unwrap_or(&0.0);
-                    let (_, node) = world
-                        .query_mut::<&mut Node>()
-                        .into_iter()
-                        .find(|(_, node)| node.f_n == f_n)
-                        .expect("node for f_n");
-                    node.triggered = d_max.sin();
+                        .find(|n| n.f_n == f_n)
+                        .map(|n| model.performance.node_on(f_n, n.freq.0, velocity))
+                        .unwrap_or_default(),
+                    NodeEvent::NodeOff { f_n } => model.performance.node_off(f_n),
+                };
+                self.send_midi(model, messages, caps);
+            }
+            InstrumentEV::SetMidiOut(midi_out) => {
+                if !midi_out {
+                    let messages = model.performance.all_off();
+                    self.send_midi(model, messages, caps);
+                }
+                model.midi_out = midi_out;
+                caps.render.render();
+            }
+            InstrumentEV::StartMidiRecording => {
+                _ = model.midi_recorder.insert(SmfRecorder::default());
+                caps.render.render();
+            }
+            InstrumentEV::StopMidiRecording => {
+                if let Some(recorder) = model.midi_recorder.take() {
+                    log::info!("exporting {} midi events", recorder.len());
+                    caps.midi.export(recorder.finish());
+                }
+                caps.render.render();
+            }
+            InstrumentEV::LoadMidi(smf) => {
+                caps.play.load_midi(smf, InstrumentEV::PlayOpLoadMidi);
+            }
+            InstrumentEV::PlayOpLoadMidi(loaded) => {
+                model.midi_loaded = loaded.is_success();
+                model.midi_playing = false;
+                if let Some(e) = loaded.error() {
+                    log::error!("midi not loaded: {e}");
+                }
+                caps.render.render();
+            }
+            InstrumentEV::MidiTransport(transport) => {
+                if model.midi_loaded {
+                    caps.play.transport(transport, move |done| {
+                        InstrumentEV::PlayOpMidiTransport(transport, done)
+                    });
+                } else {
+                    log::warn!("no midi loaded");
+                }
+            }
+            InstrumentEV::PlayOpMidiTransport(transport, done) => {
+                match done.error() {
+                    Some(e) => log::error!("midi transport: {e}"),
+                    None => match transport {
+                        Transport::Play => model.midi_playing = true,
+                        Transport::Stop => model.midi_playing = false,
+                        Transport::Seek(_) => {}
+                    },
                 }
                 caps.render.render();
             }
+            InstrumentEV::SetEnvelope(f_n, params) => {
+                let mut world = model.world.lock().expect("lock world");
+                for (_, (_, envelope)) in world
+                    .query_mut::<(&Node, &mut Envelope)>()
+                    .into_iter()
+                    .filter(|(_, (node, _))| f_n.map_or(true, |f_n| node.f_n == f_n))
+                {
+                    envelope.params = params;
+                }
+            }
             InstrumentEV::None => {}
         }
     }
@@ -269,11 +694,126 @@ impl App for Instrument {
             config: model.config.clone(),
             layout: model.layout.clone().unwrap_or_default(),
             data_out: self.get_data_out(model),
+            input_meter: model.input_meter.meter(),
+            recording: model.recording,
+            effects: model.effects.clone(),
+            node_events: model.node_events.clone(),
+            levels: self.get_levels(model),
+            midi_out: model.midi_out,
+            midi_recording: model.midi_recorder.is_some(),
+            midi_loaded: model.midi_loaded,
+            midi_playing: model.midi_playing,
+            latency: model.latency,
+            measuring_latency: model.measuring_latency,
+            error: model.error.clone(),
+            devices: model.devices.view(),
         }
     }
 }
 
 impl Instrument {
+    /// Applies snoop data once it's due to be heard
+    fn show_snoop(&self, model: &mut Model, snoop: Snoop, caps: &InstrumentCapabilities) {
+        match snoop {
+            Snoop::Output(d) => {
+                model.snooped = d;
+
+                let world = model.world.lock().expect("lock world");
+                let mut outbound = model
+                    .outbound
+                    .as_ref()
+                    .map(|e| world.get::<&mut OutboundString>(*e).ok())
+                    .flatten()
+                    .expect("get string");
+
+                outbound.update_data(model.snooped.clone(), &model.config);
+                caps.render.render();
+            }
+            Snoop::Nodes(d) => {
+                model.node_events.clear();
+
+                let (transitions, levels) = {
+                    let mut world = model.world.lock().expect("lock world");
+                    let mut transitions = vec![];
+                    let mut levels = vec![];
+                    for (f_n, d) in d {
+                        let (_, (node, envelope)) = world
+                            .query_mut::<(&mut Node, &mut Envelope)>()
+                            .into_iter()
+                            .find(|(_, (node, _))| node.f_n == f_n)
+                            .expect("node for f_n");
+                        transitions.extend(envelope.push(f_n, d.as_slice()));
+                        node.triggered = envelope.level;
+                        levels.push((f_n, envelope.level));
+                    }
+                    (transitions, levels)
+                };
+
+                for ev in transitions {
+                    self.update(InstrumentEV::NodeEvent(ev), model, caps);
+                }
+
+                let messages = levels
+                    .into_iter()
+                    .filter_map(|(f_n, level)| model.performance.node_level(f_n, level))
+                    .collect::<Vec<_>>();
+                self.send_midi(model, messages, caps);
+
+                caps.render.render();
+            }
+        }
+    }
+
+    /// Sends the frequency and Q under a pointer that holds a button
+    fn express(
+        &self,
+        model: &mut Model,
+        id: i32,
+        pt: Point2<f64>,
+        pressure: f32,
+        caps: &InstrumentCapabilities,
+    ) {
+        let expression = {
+            let world = model.world.lock().expect("world lock");
+            let mut query = world.query::<&Button>();
+            query
+                .iter()
+                .find(|(_, b)| b.finger == Some(id))
+                .map(|(_, b)| {
+                    let track = world.get::<&Track>(b.track).expect("track for button");
+                    (b.f_n, track.freq_at(&b.rect, pt, model.config.portrait))
+                })
+        };
+
+        if let Some((f_n, freq)) = expression.filter(|_| model.playing && model.configured) {
+            caps.play.update_node(PlayOperation::NodeFreq(f_n, freq));
+            caps.play.update_node(PlayOperation::NodeQ(
+                f_n,
+                pressure_q(pressure, model.config.n_buttons),
+            ));
+
+            let bend = model.performance.node_freq(f_n, freq);
+            self.send_midi(model, bend, caps);
+        }
+    }
+
+    /// Sends messages to the MIDI output when enabled and records them when recording
+    fn send_midi(
+        &self,
+        model: &mut Model,
+        messages: impl IntoIterator<Item = MidiMessage>,
+        caps: &InstrumentCapabilities,
+    ) {
+        for message in messages {
+            if let Some(recorder) = model.midi_recorder.as_mut() {
+                recorder.push(model.clock, message);
+            }
+            if model.midi_out {
+                caps.midi.send(message);
+            }
+        }
+    }
+
     fn get_nodes(&self, model: &Model) -> Vec<Node> {
         let world = model.world.lock().expect("world lock");
         model
@@ -283,6 +823,19 @@ impl Instrument {
             .collect()
     }
 
+    fn get_levels(&self, model: &Model) -> Vec<(usize, Vec<f32>)> {
+        let world = model.world.lock().expect("world lock");
+        model
+            .nodes
+            .iter()
+            .map(|e| {
+                let node = world.get::<&Node>(*e).expect("node for entity");
+                let envelope = world.get::<&Envelope>(*e).expect("envelope for entity");
+                (node.f_n, envelope.history.iter().copied().collect())
+            })
+            .collect()
+    }
+
     fn get_data_out(&self, model: &Model) -> Vec<Point2<f64>> {
         let world = model.world.lock().expect("world lock");
         model
@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+use crate::play::Latency;
+
+/// Snoop data waiting to be shown
+#[derive(Clone, Debug, PartialEq)]
+pub enum Snoop {
+    Output(Vec<f32>),
+    Nodes(Vec<(usize, Vec<f32>)>),
+}
+
+/// Holds values back until they're heard
+#[derive(Clone, Debug)]
+pub struct Delay<T> {
+    queue: VecDeque<(f64, T)>,
+    pub seconds: f64,
+}
+
+impl<T> Default for Delay<T> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            seconds: 0.0,
+        }
+    }
+}
+
+impl<T> Delay<T> {
+    /// Delays by the output's share of a measured round trip,
+    /// taken as half of it
+    pub fn for_latency(latency: &Latency) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            seconds: latency.millis / 2000.0,
+        }
+    }
+
+    /// Takes in a value produced at `at` seconds
+    pub fn push(&mut self, at: f64, value: T) {
+        self.queue.push_back((at, value));
+    }
+
+    /// Values due by `now`, oldest first
+    pub fn ready(&mut self, now: f64) -> Vec<T> {
+        let due = self
+            .queue
+            .iter()
+            .take_while(|(at, _)| at + self.seconds <= now)
+            .count();
+
+        self.queue.drain(..due).map(|(_, value)| value).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_values_once_heard() {
+        let mut delay = Delay::for_latency(&Latency {
+            samples: 4410,
+            millis: 100.0,
+        });
+
+        delay.push(1.0, Snoop::Output(vec![0.1]));
+        delay.push(1.02, Snoop::Nodes(vec![(1, vec![0.2])]));
+
+        assert!(delay.ready(1.04).is_empty());
+        assert_eq!(delay.ready(1.06), vec![Snoop::Output(vec![0.1])]);
+        assert_eq!(delay.ready(2.0), vec![Snoop::Nodes(vec![(1, vec![0.2])])]);
+        assert!(delay.ready(3.0).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+pub const LEVEL_HISTORY: usize = 64;
+
+/// Transition of a node between silent and sounding
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum NodeEvent {
+    NodeOn { f_n: usize, velocity: f32 },
+    NodeOff { f_n: usize },
+}
+
+impl Eq for NodeEvent {}
+
+/// Follower settings, times are in snoop frames
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct EnvelopeParams {
+    pub attack: f32,
+    pub release: f32,
+    pub on_threshold: f32,
+    pub off_threshold: f32,
+}
+
+impl Eq for EnvelopeParams {}
+
+impl Default for EnvelopeParams {
+    fn default() -> Self {
+        Self {
+            attack: 1.0,
+            release: 6.0,
+            on_threshold: 0.2,
+            off_threshold: 0.08,
+        }
+    }
+}
+
+/// Peak envelope of a node's snoop frames
+#[derive(Default, Clone, Debug)]
+pub struct Envelope {
+    pub params: EnvelopeParams,
+    pub level: f32,
+    pub on: bool,
+    pub history: VecDeque<f32>,
+}
+
+impl Envelope {
+    pub fn new(params: EnvelopeParams) -> Self {
+        Self {
+            params,
+            ..Default::default()
+        }
+    }
+
+    /// Follows the peak of a frame, returns a transition if one of the thresholds is crossed
+    pub fn push(&mut self, f_n: usize, frame: &[f32]) -> Option<NodeEvent> {
+        let peak = frame.iter().fold(0_f32, |acc, v| acc.max(v.abs()));
+        let frames = if peak > self.level {
+            self.params.attack
+        } else {
+            self.params.release
+        };
+        let coef = if frames > 0.0 {
+            1.0 - (-1.0 / frames).exp()
+        } else {
+            1.0
+        };
+
+        self.level += (peak - self.level) * coef;
+
+        if self.history.len() >= LEVEL_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.level);
+
+        if !self.on && self.level >= self.params.on_threshold {
+            self.on = true;
+            Some(NodeEvent::NodeOn {
+                f_n,
+                velocity: self.level.clamp(0.0, 1.0),
+            })
+        } else if self.on && self.level < self.params.off_threshold {
+            self.on = false;
+            Some(NodeEvent::NodeOff { f_n })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(amp: f32) -> Vec<f32> {
+        (0..64).map(|i| (i as f32 * 0.3).sin() * amp).collect()
+    }
+
+    #[test]
+    fn switches_on_and_off_once() {
+        let mut env = Envelope::default();
+        let mut events = vec![];
+
+        let amps = [0.0, 0.9, 0.9, 0.9].into_iter().chain([0.0; 24]);
+        for amp in amps {
+            events.extend(env.push(3, &frame(amp)));
+        }
+
+        assert_eq!(events.len(), 2, "{events:?}");
+        assert!(matches!(events[0], NodeEvent::NodeOn { f_n: 3, velocity } if velocity > 0.2));
+        assert_eq!(events[1], NodeEvent::NodeOff { f_n: 3 });
+    }
+
+    #[test]
+    fn holds_between_thresholds() {
+        let mut env = Envelope::new(EnvelopeParams {
+            attack: 0.0,
+            release: 0.0,
+            ..Default::default()
+        });
+
+        assert!(env.push(1, &frame(0.5)).is_some());
+        for amp in [0.15, 0.1, 0.19, 0.12] {
+            assert_eq!(env.push(1, &frame(amp)), None);
+        }
+        assert!(env.on);
+        assert_eq!(
+            env.push(1, &frame(0.01)),
+            Some(NodeEvent::NodeOff { f_n: 1 })
+        );
+    }
+
+    #[test]
+    fn keeps_bounded_history() {
+        let mut env = Envelope::default();
+        for _ in 0..LEVEL_HISTORY * 2 {
+            env.push(1, &frame(0.3));
+        }
+
+        assert_eq!(env.history.len(), LEVEL_HISTORY);
+    }
+}
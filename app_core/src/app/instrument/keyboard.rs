@@ -1,6 +1,9 @@
 use super::config::Config;
 use crate::{geometry::Rect, tuner::TriggerState};
 use hecs::{Bundle, Entity, World};
+use mint::Point2;
+
+const PRESSURE_Q_MUL: f32 = 9.0;
 
 #[derive(Bundle)]
 pub struct Track {
@@ -52,6 +55,32 @@ impl Track {
             freq,
         },))
     }
+
+    /// Frequency under `pt`, rising from `freq.0` at the button centre
+    /// to `freq.1` at the far end of the track
+    pub fn freq_at(&self, button_rect: &Rect, pt: Point2<f64>, portrait: bool) -> f32 {
+        let (left, right, top, bottom) = self.rect.components();
+        let centre = button_rect.center();
+        let (pos, centre, start, end) = if portrait {
+            (pt.x, centre.x, left, right)
+        } else {
+            (pt.y, centre.y, top, bottom)
+        };
+
+        let reach = (centre - start).abs().max((end - centre).abs());
+        let t = if reach > 0.0 {
+            ((pos - centre).abs() / reach).clamp(0.0, 1.0) as f32
+        } else {
+            0.0
+        };
+
+        self.freq.0 + (self.freq.1 - self.freq.0) * t
+    }
+}
+
+/// Band-pass Q of a node under touch, 0.0 pressure keeps the resting Q
+pub fn pressure_q(pressure: f32, n_buttons: usize) -> f32 {
+    (1.0 + pressure.clamp(0.0, 1.0) * PRESSURE_Q_MUL) / n_buttons.max(1) as f32
 }
 
 #[derive(Bundle)]
@@ -61,7 +90,7 @@ pub struct Button {
     pub group_button: (usize, usize),
     pub f_n: usize,
     pub freq: f32,
-    pub finger: Option<i32>
+    pub finger: Option<i32>,
 }
 
 impl Button {
@@ -105,7 +134,7 @@ impl Button {
             group_button: (group, button),
             f_n,
             freq,
-            finger: None
+            finger: None,
         },))
     }
 }
@@ -159,3 +188,62 @@ impl Keyboard {
         world.spawn((Keyboard { groups, rect },))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard() -> (World, Config) {
+        let config = Config::new(2436.0, 1125.0, 458.0, [50.0, 20.0, 10.0, 25.0]);
+        let mut world = World::new();
+        Keyboard::spawn(&mut world, &config);
+        (world, config)
+    }
+
+    #[test]
+    fn maps_track_position_to_node_range() {
+        let (world, config) = keyboard();
+
+        for (_, button) in world.query::<&Button>().iter() {
+            let track = world.get::<&Track>(button.track).unwrap();
+            let centre = button.rect.center();
+            let (left, right, top, bottom) = track.rect.components();
+
+            assert_eq!(
+                track.freq_at(&button.rect, centre, config.portrait),
+                track.freq.0
+            );
+
+            let far = [
+                Point2 {
+                    x: centre.x,
+                    y: top,
+                },
+                Point2 {
+                    x: centre.x,
+                    y: bottom,
+                },
+                Point2 {
+                    x: left,
+                    y: centre.y,
+                },
+                Point2 {
+                    x: right,
+                    y: centre.y,
+                },
+            ]
+            .into_iter()
+            .map(|pt| track.freq_at(&button.rect, pt, config.portrait))
+            .fold(f32::MIN, f32::max);
+
+            assert!((far - track.freq.1).abs() < 1e-3, "{far}");
+        }
+    }
+
+    #[test]
+    fn maps_pressure_to_q() {
+        assert_eq!(pressure_q(0.0, 10), 0.1);
+        assert_eq!(pressure_q(1.0, 10), 1.0);
+        assert_eq!(pressure_q(2.0, 10), 1.0);
+    }
+}
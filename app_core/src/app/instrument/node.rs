@@ -1,34 +1,81 @@
 use hecs::{Entity, World};
 use serde::{Deserialize, Serialize};
 
-use super::keyboard::{Button, Track};
+use super::{
+    envelope::Envelope,
+    keyboard::{Button, Track},
+    Config,
+};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Copy, Debug, Default)]
+pub enum Voice {
+    #[default]
+    SineBell,
+    FmBell,
+    NoiseChime,
+    String,
+    Organ,
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Copy, Debug)]
 
 pub struct Node {
     pub freq: (f32, f32),
     pub f_n: usize,
-    pub pan: i8,
+    /// -1.0 (first output channel) to 1.0 (last output channel)
+    pub pan: f32,
+    /// envelope level of the node output
     pub triggered: f32,
+    pub voice: Voice,
 }
 
 impl Eq for Node {}
 
 impl Node {
-    pub fn spawn(world: &mut World, freq: (f32, f32), f_n: usize, pan: i8) -> Entity {
+    pub fn spawn(world: &mut World, freq: (f32, f32), f_n: usize, pan: f32) -> Entity {
         log::debug!("node pan: {pan}");
-        world.spawn((Self { freq, f_n, pan, triggered: 0.0 },))
+        world.spawn((
+            Self {
+                freq,
+                f_n,
+                pan,
+                triggered: 0.0,
+                voice: Voice::default(),
+            },
+            Envelope::default(),
+        ))
     }
 }
 
-pub fn spawn_all_nodes(world: &mut World) -> Vec<Entity> {
-    let mut nodes = world
+/// Spawns a node per button, panned by the button position along the keyboard
+pub fn spawn_all_nodes(world: &mut World, config: &Config) -> Vec<Entity> {
+    let positioned = world
         .query::<&Button>()
         .iter()
         .map(|(_, b)| {
             let mut query = world.query_one::<&Track>(b.track).unwrap();
             let track = query.get().unwrap();
-            (track.freq, b.f_n, if track.left_hand { -1 } else { 1 })
+            let center = b.rect.center();
+            let main = if config.portrait { center.y } else { center.x };
+            (track.freq, b.f_n, main)
+        })
+        .collect::<Vec<_>>();
+
+    let (min, max) = positioned
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (_, _, main)| {
+            (min.min(*main), max.max(*main))
+        });
+
+    let mut nodes = positioned
+        .into_iter()
+        .map(|(freq, f_n, main)| {
+            let pan = if max > min {
+                ((main - min) / (max - min) * 2.0 - 1.0) as f32
+            } else {
+                0.0
+            };
+            (freq, f_n, pan)
         })
         .collect::<Vec<_>>();
 
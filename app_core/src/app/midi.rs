@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use crux_core::capability::{CapabilityContext, Operation};
+use crux_macros::Capability;
+use serde::{Deserialize, Serialize};
+
+pub use self::smf::SmfRecorder;
+
+pub mod smf;
+
+/// semitones either way of a full pitch bend
+pub const PITCH_BEND_RANGE: f32 = 2.0;
+const PITCH_BEND_CENTRE: u16 = 8192;
+const CC_EXPRESSION: u8 = 11;
+const DRUM_CHANNEL: u8 = 9;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    /// 14 bit, centred at 8192
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+}
+
+impl MidiMessage {
+    pub fn channel(&self) -> u8 {
+        match *self {
+            Self::NoteOn { channel, .. }
+            | Self::NoteOff { channel, .. }
+            | Self::PitchBend { channel, .. }
+            | Self::ControlChange { channel, .. } => channel,
+        }
+    }
+
+    /// Wire bytes of the message
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            Self::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => vec![0x90 | channel, note, velocity],
+            Self::NoteOff { channel, note } => vec![0x80 | channel, note, 0],
+            Self::PitchBend { channel, value } => {
+                vec![0xE0 | channel, (value & 0x7F) as u8, (value >> 7) as u8]
+            }
+            Self::ControlChange {
+                channel,
+                controller,
+                value,
+            } => vec![0xB0 | channel, controller, value],
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum MidiOperation {
+    Send(MidiMessage),
+    /// a recorded Standard MIDI File to save
+    Export(Vec<u8>),
+}
+
+impl Operation for MidiOperation {
+    type Output = ();
+}
+
+#[derive(Capability)]
+pub struct Midi<Ev> {
+    context: CapabilityContext<MidiOperation, Ev>,
+}
+
+impl<Ev> Midi<Ev>
+where
+    Ev: 'static,
+{
+    pub fn new(context: CapabilityContext<MidiOperation, Ev>) -> Self {
+        Self { context }
+    }
+
+    pub fn send(&self, message: MidiMessage) {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            ctx.notify_shell(MidiOperation::Send(message)).await;
+        })
+    }
+
+    pub fn export(&self, smf: Vec<u8>) {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            ctx.notify_shell(MidiOperation::Export(smf)).await;
+        })
+    }
+}
+
+/// Nearest note and the pitch bend that reaches `freq` from it
+pub fn note_for_freq(freq: f32) -> (u8, u16) {
+    let pitch = 69.0 + 12.0 * (freq.max(1.0) / 440.0).log2();
+    let note = pitch.round().clamp(0.0, 127.0);
+
+    (note as u8, pitch_bend(pitch - note))
+}
+
+fn pitch_bend(semitones: f32) -> u16 {
+    let bend = (semitones / PITCH_BEND_RANGE).clamp(-1.0, 1.0);
+    (PITCH_BEND_CENTRE as f32 + bend * (PITCH_BEND_CENTRE - 1) as f32).round() as u16
+}
+
+/// Channel of a node, each node gets its own so that it can bend independently
+pub fn channel_for(f_n: usize) -> u8 {
+    let channel = (f_n.saturating_sub(1) % 15) as u8;
+    if channel >= DRUM_CHANNEL {
+        channel + 1
+    } else {
+        channel
+    }
+}
+
+/// Notes sounding per node, turning node events into MIDI messages
+#[derive(Default, Debug)]
+pub struct Performance {
+    notes: BTreeMap<usize, (u8, u8)>,
+}
+
+impl Performance {
+    pub fn node_on(&mut self, f_n: usize, freq: f32, velocity: f32) -> Vec<MidiMessage> {
+        let mut messages = self.node_off(f_n);
+        let channel = channel_for(f_n);
+        let (note, value) = note_for_freq(freq);
+
+        self.notes.insert(f_n, (note, 0));
+
+        messages.push(MidiMessage::PitchBend { channel, value });
+        messages.push(MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity: ((velocity.clamp(0.0, 1.0) * 126.0).round() as u8) + 1,
+        });
+
+        messages
+    }
+
+    pub fn node_off(&mut self, f_n: usize) -> Vec<MidiMessage> {
+        self.notes
+            .remove(&f_n)
+            .map(|(note, _)| MidiMessage::NoteOff {
+                channel: channel_for(f_n),
+                note,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Bends a sounding note towards `freq`
+    pub fn node_freq(&self, f_n: usize, freq: f32) -> Option<MidiMessage> {
+        self.notes.get(&f_n).map(|(note, _)| {
+            let pitch = 69.0 + 12.0 * (freq.max(1.0) / 440.0).log2();
+            MidiMessage::PitchBend {
+                channel: channel_for(f_n),
+                value: pitch_bend(pitch - *note as f32),
+            }
+        })
+    }
+
+    /// Expression controller of a sounding note, when its value changes
+    pub fn node_level(&mut self, f_n: usize, level: f32) -> Option<MidiMessage> {
+        let value = (level.clamp(0.0, 1.0) * 127.0).round() as u8;
+        self.notes
+            .get_mut(&f_n)
+            .filter(|(_, last)| *last != value)
+            .map(|(_, last)| {
+                *last = value;
+                MidiMessage::ControlChange {
+                    channel: channel_for(f_n),
+                    controller: CC_EXPRESSION,
+                    value,
+                }
+            })
+    }
+
+    /// Note offs for everything still sounding
+    pub fn all_off(&mut self) -> Vec<MidiMessage> {
+        let sounding = self.notes.keys().copied().collect::<Vec<_>>();
+        sounding
+            .into_iter()
+            .flat_map(|f_n| self.node_off(f_n))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_frequencies_to_notes_and_bends() {
+        assert_eq!(note_for_freq(440.0), (69, PITCH_BEND_CENTRE));
+        assert_eq!(note_for_freq(261.63).0, 60);
+
+        let (note, bend) = note_for_freq(440.0 * 2_f32.powf(0.25 / 12.0));
+        assert_eq!(note, 69);
+        assert!(bend > PITCH_BEND_CENTRE);
+
+        let (note, bend) = note_for_freq(440.0 * 2_f32.powf(-0.25 / 12.0));
+        assert_eq!(note, 69);
+        assert!(bend < PITCH_BEND_CENTRE);
+    }
+
+    #[test]
+    fn skips_drum_channel() {
+        let channels = (1..=15).map(channel_for).collect::<Vec<_>>();
+
+        assert!(!channels.contains(&DRUM_CHANNEL));
+        assert_eq!(channels.iter().max(), Some(&15));
+    }
+
+    #[test]
+    fn turns_node_events_into_messages() {
+        let mut performance = Performance::default();
+
+        let on = performance.node_on(2, 220.0, 1.0);
+        assert_eq!(
+            on,
+            vec![
+                MidiMessage::PitchBend {
+                    channel: 1,
+                    value: PITCH_BEND_CENTRE
+                },
+                MidiMessage::NoteOn {
+                    channel: 1,
+                    note: 57,
+                    velocity: 127
+                }
+            ]
+        );
+        assert!(performance.node_level(2, 0.5).is_some());
+        assert!(performance.node_level(2, 0.5).is_none());
+        assert!(performance.node_level(3, 0.5).is_none());
+        assert_eq!(
+            performance.node_off(2),
+            vec![MidiMessage::NoteOff {
+                channel: 1,
+                note: 57
+            }]
+        );
+        assert!(performance.node_off(2).is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{anyhow, bail, ensure, Result};
+
+use super::MidiMessage;
+
+/// ticks per quarter note
+pub const DIVISION: u16 = 480;
+/// microseconds per quarter note, 120 bpm
+const TEMPO: u32 = 500_000;
+
+/// Timestamped messages of a session, written out as a Standard MIDI File (type 1)
+/// with a tempo track and a track per channel
+#[derive(Default, Debug, Clone)]
+pub struct SmfRecorder {
+    start: Option<f64>,
+    events: Vec<(f64, MidiMessage)>,
+}
+
+impl SmfRecorder {
+    /// `time` is in seconds, from any origin
+    pub fn push(&mut self, time: f64, message: MidiMessage) {
+        let start = *self.start.get_or_insert(time);
+        self.events.push(((time - start).max(0.0), message));
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.events
+            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut channels = self
+            .events
+            .iter()
+            .map(|(_, m)| m.channel())
+            .collect::<Vec<_>>();
+        channels.sort();
+        channels.dedup();
+
+        let mut tempo_track = vec![];
+        tempo_track.extend(meta(0, 0x03, b"Red Siren"));
+        tempo_track.extend(meta(0, 0x51, &TEMPO.to_be_bytes()[1..]));
+        tempo_track.extend(meta(0, 0x2F, &[]));
+
+        let mut bytes = vec![];
+        bytes.extend(b"MThd");
+        bytes.extend(6_u32.to_be_bytes());
+        bytes.extend(1_u16.to_be_bytes());
+        bytes.extend((channels.len() as u16 + 1).to_be_bytes());
+        bytes.extend(DIVISION.to_be_bytes());
+        bytes.extend(chunk(b"MTrk", tempo_track));
+
+        for channel in channels {
+            let mut track = vec![];
+            let mut last_tick = 0;
+
+            for (time, message) in self.events.iter().filter(|(_, m)| m.channel() == channel) {
+                let tick = ticks(*time);
+                track.extend(vlq(tick - last_tick));
+                track.extend(message.bytes());
+                last_tick = tick;
+            }
+
+            track.extend(meta(0, 0x2F, &[]));
+            bytes.extend(chunk(b"MTrk", track));
+        }
+
+        bytes
+    }
+}
+
+/// Reads the channel messages of a Standard MIDI File (type 0 or 1),
+/// merged across tracks and timed in seconds following the tempo map
+pub fn read(bytes: &[u8]) -> Result<Vec<(f64, MidiMessage)>> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    ensure!(reader.take(4)? == b"MThd", "not a midi file");
+    let header_len = reader.u32()? as usize;
+    let header = reader.take(header_len)?;
+    ensure!(header_len >= 6, "short midi header");
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    let n_tracks = u16::from_be_bytes([header[2], header[3]]);
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    ensure!(format < 2, "unsupported midi format {format}");
+
+    let mut tempi = vec![(0_u64, TEMPO)];
+    let mut events = vec![];
+
+    for _ in 0..n_tracks {
+        let id = reader.take(4)?;
+        let len = reader.u32()? as usize;
+        let data = reader.take(len)?;
+        if id != b"MTrk" {
+            log::debug!("skipping unknown chunk");
+            continue;
+        }
+
+        read_track(data, &mut tempi, &mut events)?;
+    }
+
+    tempi.sort_by_key(|(tick, _)| *tick);
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let seconds_per_tick = |tempo: u32| match division {
+        d if d & 0x8000 != 0 => {
+            let fps = -((d >> 8) as i8) as f64;
+            1.0 / (fps * (d & 0xFF) as f64)
+        }
+        d => tempo as f64 / 1_000_000.0 / d.max(1) as f64,
+    };
+
+    let mut tempo_idx = 0;
+    let mut last_tick = 0;
+    let mut time = 0.0;
+
+    Ok(events
+        .into_iter()
+        .map(|(tick, message)| {
+            while tempo_idx + 1 < tempi.len() && tempi[tempo_idx + 1].0 <= tick {
+                let (change, _) = tempi[tempo_idx + 1];
+                time += (change - last_tick) as f64 * seconds_per_tick(tempi[tempo_idx].1);
+                last_tick = change;
+                tempo_idx += 1;
+            }
+            time += (tick - last_tick) as f64 * seconds_per_tick(tempi[tempo_idx].1);
+            last_tick = tick;
+
+            (time, message)
+        })
+        .collect())
+}
+
+fn read_track(
+    data: &[u8],
+    tempi: &mut Vec<(u64, u32)>,
+    events: &mut Vec<(u64, MidiMessage)>,
+) -> Result<()> {
+    let mut reader = Reader {
+        bytes: data,
+        pos: 0,
+    };
+    let mut tick = 0_u64;
+    let mut running = None;
+
+    while !reader.is_done() {
+        tick += reader.vlq()? as u64;
+
+        let status = match reader.peek()? {
+            b if b & 0x80 != 0 => {
+                reader.pos += 1;
+                b
+            }
+            _ => running.ok_or(anyhow!("data byte without status"))?,
+        };
+
+        match status {
+            0xFF => {
+                let kind = reader.byte()?;
+                let len = reader.vlq()? as usize;
+                let data = reader.take(len)?;
+                match kind {
+                    0x51 if len == 3 => {
+                        tempi.push((tick, u32::from_be_bytes([0, data[0], data[1], data[2]])))
+                    }
+                    0x2F => break,
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = reader.vlq()? as usize;
+                reader.take(len)?;
+            }
+            0xF1..=0xFE => bail!("unexpected system message {status:#x}"),
+            _ => {
+                running = Some(status);
+                let channel = status & 0x0F;
+
+                let message = match status & 0xF0 {
+                    0x80 => {
+                        let [note, _] = reader.data2()?;
+                        Some(MidiMessage::NoteOff { channel, note })
+                    }
+                    0x90 => {
+                        let [note, velocity] = reader.data2()?;
+                        Some(if velocity == 0 {
+                            MidiMessage::NoteOff { channel, note }
+                        } else {
+                            MidiMessage::NoteOn {
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        })
+                    }
+                    0xB0 => {
+                        let [controller, value] = reader.data2()?;
+                        Some(MidiMessage::ControlChange {
+                            channel,
+                            controller,
+                            value,
+                        })
+                    }
+                    0xE0 => {
+                        let [lsb, msb] = reader.data2()?;
+                        Some(MidiMessage::PitchBend {
+                            channel,
+                            value: lsb as u16 | ((msb as u16) << 7),
+                        })
+                    }
+                    0xA0 => {
+                        reader.data2()?;
+                        None
+                    }
+                    _ => {
+                        reader.byte()?;
+                        None
+                    }
+                };
+
+                events.extend(message.map(|m| (tick, m)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or(anyhow!("unexpected end of midi data"))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn data2(&mut self) -> Result<[u8; 2]> {
+        Ok([self.byte()? & 0x7F, self.byte()? & 0x7F])
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        ensure!(end <= self.bytes.len(), "unexpected end of midi data");
+        let data = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let data = self.take(4)?;
+        Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32> {
+        let mut value = 0_u32;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            value = (value << 7) | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("variable-length quantity too long")
+    }
+}
+
+fn ticks(time: f64) -> u32 {
+    (time * DIVISION as f64 * 1_000_000.0 / TEMPO as f64).round() as u32
+}
+
+fn chunk(id: &[u8; 4], data: Vec<u8>) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((data.len() as u32).to_be_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+fn meta(delta: u32, kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vlq(delta);
+    bytes.extend([0xFF, kind]);
+    bytes.extend(vlq(data.len() as u32));
+    bytes.extend(data);
+    bytes
+}
+
+/// variable-length quantity, 7 bits per byte, most significant first
+fn vlq(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_variable_length_quantities() {
+        assert_eq!(vlq(0), vec![0x00]);
+        assert_eq!(vlq(0x7F), vec![0x7F]);
+        assert_eq!(vlq(0x80), vec![0x81, 0x00]);
+        assert_eq!(vlq(0x3FFF), vec![0xFF, 0x7F]);
+        assert_eq!(vlq(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn writes_track_per_channel() {
+        let mut recorder = SmfRecorder::default();
+        recorder.push(
+            10.0,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 60,
+                velocity: 100,
+            },
+        );
+        recorder.push(
+            10.5,
+            MidiMessage::NoteOn {
+                channel: 1,
+                note: 64,
+                velocity: 100,
+            },
+        );
+        recorder.push(
+            11.0,
+            MidiMessage::NoteOff {
+                channel: 0,
+                note: 60,
+            },
+        );
+
+        let bytes = recorder.finish();
+
+        assert_eq!(&bytes[..4], b"MThd");
+        assert_eq!(&bytes[8..10], &1_u16.to_be_bytes());
+        assert_eq!(&bytes[10..12], &3_u16.to_be_bytes());
+        assert_eq!(&bytes[12..14], &DIVISION.to_be_bytes());
+        assert_eq!(bytes.windows(4).filter(|w| w == b"MTrk").count(), 3);
+
+        // first channel track: note on at 0, note off one second (960 ticks) later
+        let track = bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| w == b"MTrk")
+            .nth(1)
+            .map(|(i, _)| &bytes[i + 8..])
+            .unwrap();
+        assert_eq!(&track[..4], &[0x00, 0x90, 60, 100]);
+        assert_eq!(&track[4..9], &[0x87, 0x40, 0x80, 60, 0]);
+    }
+
+    #[test]
+    fn reads_back_recorded_sessions() {
+        let messages = [
+            (
+                2.0,
+                MidiMessage::PitchBend {
+                    channel: 3,
+                    value: 9000,
+                },
+            ),
+            (
+                2.0,
+                MidiMessage::NoteOn {
+                    channel: 3,
+                    note: 57,
+                    velocity: 90,
+                },
+            ),
+            (
+                2.25,
+                MidiMessage::ControlChange {
+                    channel: 3,
+                    controller: 11,
+                    value: 64,
+                },
+            ),
+            (
+                3.5,
+                MidiMessage::NoteOff {
+                    channel: 3,
+                    note: 57,
+                },
+            ),
+        ];
+        let mut recorder = SmfRecorder::default();
+        for (time, message) in messages {
+            recorder.push(time, message);
+        }
+
+        let events = read(recorder.finish().as_slice()).unwrap();
+
+        assert_eq!(events.len(), messages.len());
+        for ((time, message), (expected_time, expected)) in events.iter().zip(messages) {
+            assert!((time - (expected_time - 2.0)).abs() < 1e-3);
+            assert_eq!(*message, expected);
+        }
+    }
+
+    #[test]
+    fn follows_running_status_and_tempo_changes() {
+        let mut track = vec![];
+        // 60 bpm from the start
+        track.extend(meta(0, 0x51, &1_000_000_u32.to_be_bytes()[1..]));
+        track.extend([0x00, 0x90, 60, 100]);
+        // running status, zero velocity note on is a note off
+        track.extend(vlq(DIVISION as u32));
+        track.extend([60, 0]);
+        track.extend(meta(0, 0x2F, &[]));
+
+        let mut bytes = vec![];
+        bytes.extend(b"MThd");
+        bytes.extend(6_u32.to_be_bytes());
+        bytes.extend(0_u16.to_be_bytes());
+        bytes.extend(1_u16.to_be_bytes());
+        bytes.extend(DIVISION.to_be_bytes());
+        bytes.extend(chunk(b"MTrk", track));
+
+        let events = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                (
+                    0.0,
+                    MidiMessage::NoteOn {
+                        channel: 0,
+                        note: 60,
+                        velocity: 100
+                    }
+                ),
+                (
+                    1.0,
+                    MidiMessage::NoteOff {
+                        channel: 0,
+                        note: 60
+                    }
+                ),
+            ]
+        );
+        assert!(read(b"RIFF").is_err());
+    }
+}
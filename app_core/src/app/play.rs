@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crux_core::capability::{CapabilityContext, Operation};
 use crux_macros::Capability;
 use serde::{Deserialize, Serialize};
@@ -6,29 +8,141 @@ use crate::tuner::TuningValue;
 
 use super::instrument::{Config, Node};
 
+pub use self::analysis::{AnalysisConfig, Binning, Window};
+pub use self::devices::{AudioDevice, DeviceOption, DeviceSelection, Devices, DevicesVM};
+pub use self::effects::{FxChain, FxUnit};
+pub use self::meter::{InputLevel, InputMeter, Meter};
+
+pub mod analysis;
+pub mod devices;
+pub mod effects;
+pub mod meter;
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum PlayOperation {
     Permissions,
     InstallAU,
     Suspend,
     Resume,
-    Capture(bool),
+    /// start capturing spectra with the given analysis, or stop
+    Capture(Option<AnalysisConfig>),
     QueryInputDevices,
     QueryOutputDevices,
+    /// devices the next `InstallAU` opens streams on
+    SelectDevices(DeviceSelection),
     Config(Config, Vec<Node>, Vec<TuningValue>),
-    Input(Vec<Vec<f32>>),
-    SendSnoops
+    /// a block of input channels, shared so that a processor can refill it once the core is done
+    Input(Arc<Vec<Vec<f32>>>),
+    SendSnoops,
+    /// sample rate negotiated with the device, reported by the shell
+    SampleRate(f64),
+    /// output channels of the device
+    OutputChannels(usize),
+    /// sensitised (band-pass) frequency of node `f_n`
+    NodeSensitivity(usize, f32),
+    /// band-pass Q of node `f_n`
+    NodeQ(usize, f32),
+    /// input gain of node `f_n` as tuning amplitude
+    NodeGain(usize, f32),
+    /// output frequency of node `f_n`
+    NodeFreq(usize, f32),
+    /// pan position of node `f_n`, -1.0 to 1.0 across the output channels
+    NodePan(usize, f32),
+    /// output gain of the mixed nodes
+    MasterGain(f32),
+    /// record the output, up to the given length in seconds
+    StartRecording(f64),
+    StopRecording,
+    Effects(FxChain),
+    /// a Standard MIDI File to drive the nodes instead of the input
+    LoadMidi(Vec<u8>),
+    Transport(Transport),
+    /// measure the ambient noise floor in each tuned band over the given seconds
+    Calibrate(Vec<TuningValue>, f64),
+    /// noise floor of each node `f_n` from an earlier calibration
+    NoiseFloor(Vec<(usize, f32)>),
+    /// play a test signal and listen for it to measure the round trip
+    MeasureLatency,
 }
 
 impl Eq for PlayOperation {}
 
+/// Transport of a loaded MIDI file
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Transport {
+    Play,
+    Stop,
+    /// position in seconds
+    Seek(f64),
+}
+
+impl Eq for Transport {}
+
+/// Why the audio unit couldn't do what was asked
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, thiserror::Error)]
+pub enum PlayError {
+    #[error("microphone access was denied")]
+    PermissionDenied,
+    #[error("audio device unavailable: {0}")]
+    DeviceUnavailable(String),
+    /// a running stream was lost, e.g. to an unplugged device
+    #[error("audio stream disconnected")]
+    StreamDisconnected,
+    #[error("could not build the instrument: {0}")]
+    GraphConstruction(String),
+    /// the shell and the unit don't speak the same version of the messages
+    #[error("unexpected message: {0}")]
+    ProtocolMismatch(String),
+    /// the operation doesn't apply in the current state
+    #[error("{0}")]
+    Operation(String),
+}
+
+impl Eq for PlayError {}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum PlayOperationOutput {
     Success,
-    Failure
+    Failure(PlayError),
+    /// devices answering a query
+    Devices(Vec<AudioDevice>),
+    /// capture started, at the input's sample rate
+    Capturing(f64),
 }
 
 impl Eq for PlayOperationOutput {}
+
+impl PlayOperationOutput {
+    pub fn is_success(&self) -> bool {
+        !matches!(self, Self::Failure(_))
+    }
+
+    pub fn error(self) -> Option<PlayError> {
+        match self {
+            Self::Failure(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Result<(), PlayError>> for PlayOperationOutput {
+    fn from(result: Result<(), PlayError>) -> Self {
+        match result {
+            Ok(_) => Self::Success,
+            Err(e) => Self::Failure(e),
+        }
+    }
+}
+
+impl From<Result<Vec<AudioDevice>, PlayError>> for PlayOperationOutput {
+    fn from(result: Result<Vec<AudioDevice>, PlayError>) -> Self {
+        match result {
+            Ok(devices) => Self::Devices(devices),
+            Err(e) => Self::Failure(e),
+        }
+    }
+}
+
 impl Operation for PlayOperation {
     type Output = PlayOperationOutput;
 }
@@ -37,11 +151,39 @@ impl Operation for PlayOperationOutput {
     type Output = ();
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct PeaksData {
+    /// fundamental frequency and confidence
+    pub pitch: Option<(f32, f32)>,
+    /// frequency, magnitude and confidence, loudest first
+    pub peaks: Vec<(f32, f32, f32)>,
+}
+
+impl Eq for PeaksData {}
+
+/// Round trip from the output back to the input
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Latency {
+    pub samples: usize,
+    pub millis: f64,
+}
+
+impl Eq for Latency {}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum CaptureOutput {
     CaptureFFT(Vec<(f32, f32)>),
     CaptureData(Vec<f32>),
-    CaptureNodesData(Vec<(usize, Vec<f32>)>)
+    CaptureNodesData(Vec<(usize, Vec<f32>)>),
+    CapturePeaks(PeaksData),
+    /// WAV bytes of a finished recording
+    CaptureRecording(Vec<u8>),
+    /// measured noise floor of each node `f_n`, RMS of its band of the input
+    CaptureNoiseFloor(Vec<(usize, f32)>),
+    /// raw input levels, at most every metering interval
+    CaptureInputLevel(InputLevel),
+    /// measured round trip, `None` when the test signal wasn't heard
+    CaptureLatency(Option<Latency>),
 }
 
 impl Eq for CaptureOutput {}
@@ -66,7 +208,7 @@ where
     pub fn configure<F>(&self, config: &Config, nodes: &[Node], tuning: &[TuningValue], f: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
         let config = config.clone();
@@ -77,46 +219,88 @@ where
             let done = ctx
                 .request_from_shell(PlayOperation::Config(config, nodes, tuning))
                 .await;
-            ctx.update_app(f(done == PlayOperationOutput::Success));
+            ctx.update_app(f(done));
+        })
+    }
+
+    pub fn effects<F>(&self, chain: &FxChain, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+        let chain = chain.clone();
+
+        self.context.spawn(async move {
+            let done = ctx.request_from_shell(PlayOperation::Effects(chain)).await;
+            ctx.update_app(f(done));
+        })
+    }
+
+    pub fn load_midi<F>(&self, smf: Vec<u8>, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let done = ctx.request_from_shell(PlayOperation::LoadMidi(smf)).await;
+            ctx.update_app(f(done));
+        })
+    }
+
+    pub fn transport<F>(&self, transport: Transport, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let done = ctx
+                .request_from_shell(PlayOperation::Transport(transport))
+                .await;
+            ctx.update_app(f(done));
         })
     }
 
     pub fn play<F>(&self, f: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
 
         self.context.spawn(async move {
             let playing = ctx.request_from_shell(PlayOperation::Resume).await;
-            ctx.update_app(f(playing == PlayOperationOutput::Success));
+            ctx.update_app(f(playing));
         })
     }
 
     pub fn pause<F>(&self, f: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
 
         self.context.spawn(async move {
             let paused = ctx.request_from_shell(PlayOperation::Suspend).await;
-            ctx.update_app(f(paused == PlayOperationOutput::Success));
+            ctx.update_app(f(paused));
         })
     }
 
     pub fn install_au<F>(&self, f: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
 
         self.context.spawn(async move {
             let done = ctx.request_from_shell(PlayOperation::InstallAU).await;
-            ctx.update_app(f(done == PlayOperationOutput::Success));
+            ctx.update_app(f(done));
         })
     }
     
@@ -129,38 +313,156 @@ where
         })
     }
 
+    pub fn tune_node(&self, (f_n, freq, amp): TuningValue) {
+        self.update_node(PlayOperation::NodeSensitivity(f_n, freq));
+        self.update_node(PlayOperation::NodeGain(f_n, amp));
+    }
+
+    pub fn calibrate<F>(&self, tuning: &[TuningValue], seconds: f64, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+        let tuning = Vec::from(tuning);
+
+        self.context.spawn(async move {
+            let calibrating = ctx
+                .request_from_shell(PlayOperation::Calibrate(tuning, seconds))
+                .await;
+            ctx.update_app(f(calibrating));
+        })
+    }
+
+    pub fn measure_latency<F>(&self, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let measuring = ctx.request_from_shell(PlayOperation::MeasureLatency).await;
+            ctx.update_app(f(measuring));
+        })
+    }
+
+    pub fn noise_floor(&self, floor: &[(usize, f32)]) {
+        self.update_node(PlayOperation::NoiseFloor(Vec::from(floor)));
+    }
+
+    pub fn update_node(&self, op: PlayOperation) {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            ctx.notify_shell(op).await;
+        })
+    }
+
+    pub fn start_recording<F>(&self, max_s: f64, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let recording = ctx
+                .request_from_shell(PlayOperation::StartRecording(max_s))
+                .await;
+            ctx.update_app(f(recording));
+        })
+    }
+
+    pub fn stop_recording<F>(&self, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let stopped = ctx.request_from_shell(PlayOperation::StopRecording).await;
+            ctx.update_app(f(stopped));
+        })
+    }
+
+    pub fn query_input_devices<F>(&self, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let devices = ctx.request_from_shell(PlayOperation::QueryInputDevices).await;
+            ctx.update_app(f(devices));
+        })
+    }
+
+    pub fn query_output_devices<F>(&self, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            let devices = ctx.request_from_shell(PlayOperation::QueryOutputDevices).await;
+            ctx.update_app(f(devices));
+        })
+    }
+
+    pub fn select_devices<F>(&self, selection: &DeviceSelection, f: F)
+    where
+        Ev: 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
+    {
+        let ctx = self.context.clone();
+        let selection = selection.clone();
+
+        self.context.spawn(async move {
+            let selected = ctx
+                .request_from_shell(PlayOperation::SelectDevices(selection))
+                .await;
+            ctx.update_app(f(selected));
+        })
+    }
+
     pub fn permissions<F>(&self, f: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
 
         self.context.spawn(async move {
             let granted = ctx.request_from_shell(PlayOperation::Permissions).await;
-            ctx.update_app(f(granted == PlayOperationOutput::Success));
+            ctx.update_app(f(granted));
         })
     }
-    pub fn capture_fft<F>(&self, notify: F)
+    pub fn capture_fft<F>(&self, analysis: AnalysisConfig, notify: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static,
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static,
     {
         let ctx = self.context.clone();
         self.context.spawn(async move {
-            let capturing = ctx.request_from_shell(PlayOperation::Capture(true)).await;
-            ctx.update_app(notify(capturing == PlayOperationOutput::Success));
+            let capturing = ctx
+                .request_from_shell(PlayOperation::Capture(Some(analysis)))
+                .await;
+            ctx.update_app(notify(capturing));
         });
     }
 
     pub fn stop_capture_fft<F>(&self, notify: F)
     where
         Ev: 'static,
-        F: Fn(bool) -> Ev + Send + 'static, {
+        F: Fn(PlayOperationOutput) -> Ev + Send + 'static, {
         let ctx = self.context.clone();
         self.context.spawn(async move {
-            let stopped = ctx.request_from_shell(PlayOperation::Capture(false)).await;
-            ctx.update_app(notify(stopped == PlayOperationOutput::Success));
+            let stopped = ctx.request_from_shell(PlayOperation::Capture(None)).await;
+            ctx.update_app(notify(stopped));
         })
     }
 }
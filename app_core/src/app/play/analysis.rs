@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+const MIN_FFT_SIZE: usize = 256;
+const MAX_FFT_SIZE: usize = 16384;
+/// least hop as a fraction of the frame, bounding the frame rate
+const MAX_OVERLAP: usize = 8;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub enum Window {
+    #[default]
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    /// accurate magnitudes, wide main lobe
+    FlatTop,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub enum Binning {
+    /// FFT bins as they are
+    #[default]
+    Linear,
+    /// given number of log-spaced bins over the analysed range
+    Log(usize),
+    /// standard 1/3-octave bands
+    ThirdOctave,
+}
+
+/// How captured input is turned into spectra
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct AnalysisConfig {
+    pub window: Window,
+    /// samples per frame, a power of two
+    pub fft_size: usize,
+    /// samples between the starts of consecutive frames, overlapping when less than `fft_size`
+    pub hop: usize,
+    pub binning: Binning,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            window: Window::default(),
+            fft_size: 4096,
+            hop: 4096,
+            binning: Binning::default(),
+        }
+    }
+}
+
+impl AnalysisConfig {
+    /// The config as analysed: a supported power of two frame
+    /// and a hop between an eighth of it and all of it
+    pub fn sanitised(self) -> Self {
+        let fft_size = self
+            .fft_size
+            .next_power_of_two()
+            .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE);
+
+        Self {
+            fft_size,
+            hop: self.hop.clamp(fft_size / MAX_OVERLAP, fft_size),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+/// An audio device streams can be opened on
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AudioDevice {
+    /// backend specific, stable while the device is connected
+    pub id: String,
+    pub name: String,
+    /// most channels the device offers
+    pub channels: usize,
+    pub sample_rates: Vec<f64>,
+}
+
+impl Eq for AudioDevice {}
+
+/// Devices to open streams on, the system default where `None`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct DeviceSelection {
+    pub input: Option<String>,
+    pub output: Option<String>,
+}
+
+/// A device as offered for choosing
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct DeviceOption {
+    pub device: AudioDevice,
+    pub selected: bool,
+}
+
+impl Eq for DeviceOption {}
+
+/// Devices to choose from, none selected meaning the system default
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct DevicesVM {
+    pub inputs: Vec<DeviceOption>,
+    pub outputs: Vec<DeviceOption>,
+}
+
+/// Devices last listed by the unit and the ones chosen
+#[derive(Clone, Debug, Default)]
+pub struct Devices {
+    pub inputs: Vec<AudioDevice>,
+    pub outputs: Vec<AudioDevice>,
+    pub selection: DeviceSelection,
+}
+
+impl Devices {
+    /// Updates the listed inputs, `true` when the chosen one is gone
+    /// and the selection fell back to the default
+    pub fn set_inputs(&mut self, devices: Vec<AudioDevice>) -> bool {
+        self.inputs = devices;
+        keep_listed(&mut self.selection.input, self.inputs.as_slice())
+    }
+
+    /// Updates the listed outputs, `true` when the chosen one is gone
+    /// and the selection fell back to the default
+    pub fn set_outputs(&mut self, devices: Vec<AudioDevice>) -> bool {
+        self.outputs = devices;
+        keep_listed(&mut self.selection.output, self.outputs.as_slice())
+    }
+
+    pub fn view(&self) -> DevicesVM {
+        DevicesVM {
+            inputs: options(self.inputs.as_slice(), &self.selection.input),
+            outputs: options(self.outputs.as_slice(), &self.selection.output),
+        }
+    }
+}
+
+fn keep_listed(selected: &mut Option<String>, devices: &[AudioDevice]) -> bool {
+    let gone = selected
+        .as_ref()
+        .map_or(false, |id| !devices.iter().any(|device| &device.id == id));
+
+    if gone {
+        _ = selected.take();
+    }
+
+    gone
+}
+
+fn options(devices: &[AudioDevice], selected: &Option<String>) -> Vec<DeviceOption> {
+    devices
+        .iter()
+        .map(|device| DeviceOption {
+            device: device.clone(),
+            selected: selected.as_ref() == Some(&device.id),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str) -> AudioDevice {
+        AudioDevice {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            channels: 2,
+            sample_rates: vec![44100.0, 48000.0],
+        }
+    }
+
+    #[test]
+    fn falls_back_to_default_when_a_device_goes() {
+        let mut devices = Devices::default();
+        assert!(!devices.set_inputs(vec![device("mic"), device("usb")]));
+
+        devices.selection.input = Some("usb".to_string());
+        let vm = devices.view();
+        assert!(!vm.inputs[0].selected);
+        assert!(vm.inputs[1].selected);
+
+        assert!(!devices.set_inputs(vec![device("usb")]));
+        assert_eq!(devices.selection.input.as_deref(), Some("usb"));
+
+        assert!(devices.set_inputs(vec![device("mic")]));
+        assert_eq!(devices.selection.input, None);
+        assert!(devices.view().inputs.iter().all(|option| !option.selected));
+    }
+}
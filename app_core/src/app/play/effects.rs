@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum FxUnit {
+    Reverb {
+        room_size: f32,
+        time: f32,
+        mix: f32,
+    },
+    Delay {
+        time: f32,
+        feedback: f32,
+        mix: f32,
+    },
+    /// parametric band, gain is linear amplitude
+    Eq {
+        freq: f32,
+        q: f32,
+        gain: f32,
+    },
+    Chorus {
+        separation: f32,
+        variation: f32,
+        mod_frequency: f32,
+    },
+    /// look-ahead limiter, times in seconds
+    Limiter {
+        attack: f32,
+        release: f32,
+    },
+}
+
+impl Eq for FxUnit {}
+
+/// Ordered units applied to every output channel after the node mix
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct FxChain(pub Vec<FxUnit>);
+
+impl Eq for FxChain {}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// quietest level shown, in dBFS
+pub const METER_FLOOR_DB: f32 = -96.0;
+/// updates the clip indicator stays lit for after a clip
+const CLIP_HOLD: usize = 30;
+
+/// Levels of the raw input over one metering interval, linear full scale
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct InputLevel {
+    pub peak: f32,
+    pub rms: f32,
+    /// samples at or over full scale
+    pub clips: usize,
+}
+
+impl Eq for InputLevel {}
+
+/// Input meter as shown, in dBFS
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Meter {
+    pub peak: f32,
+    pub rms: f32,
+    pub clipping: bool,
+}
+
+impl Eq for Meter {}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            peak: METER_FLOOR_DB,
+            rms: METER_FLOOR_DB,
+            clipping: false,
+        }
+    }
+}
+
+/// Follows input levels, holding the clip indicator for a while
+#[derive(Clone, Debug, Default)]
+pub struct InputMeter {
+    level: InputLevel,
+    clip_hold: usize,
+}
+
+impl InputMeter {
+    pub fn push(&mut self, level: InputLevel) {
+        if level.clips > 0 {
+            self.clip_hold = CLIP_HOLD;
+        } else {
+            self.clip_hold = self.clip_hold.saturating_sub(1);
+        }
+        self.level = level;
+    }
+
+    pub fn meter(&self) -> Meter {
+        Meter {
+            peak: dbfs(self.level.peak),
+            rms: dbfs(self.level.rms),
+            clipping: self.clip_hold > 0,
+        }
+    }
+}
+
+fn dbfs(level: f32) -> f32 {
+    (20.0 * level.log10()).max(METER_FLOOR_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_levels_in_dbfs() {
+        let mut meter = InputMeter::default();
+        assert_eq!(meter.meter(), Meter::default());
+
+        meter.push(InputLevel {
+            peak: 1.0,
+            rms: 0.1,
+            clips: 0,
+        });
+        let shown = meter.meter();
+        assert_eq!(shown.peak, 0.0);
+        assert!((shown.rms + 20.0).abs() < 1e-4);
+        assert!(!shown.clipping);
+    }
+
+    #[test]
+    fn holds_the_clip_indicator() {
+        let mut meter = InputMeter::default();
+        meter.push(InputLevel {
+            peak: 1.0,
+            rms: 0.5,
+            clips: 3,
+        });
+
+        for _ in 0..CLIP_HOLD - 1 {
+            meter.push(InputLevel::default());
+            assert!(meter.meter().clipping);
+        }
+
+        meter.push(InputLevel::default());
+        assert!(!meter.meter().clipping);
+        assert_eq!(meter.meter().peak, METER_FLOOR_DB);
+    }
+}
@@ -0,0 +1,36 @@
+use crux_core::capability::{CapabilityContext, Operation};
+use crux_macros::Capability;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum ShareOperation {
+    /// WAV bytes of a finished recording to save
+    Recording(Vec<u8>),
+}
+
+impl Operation for ShareOperation {
+    type Output = ();
+}
+
+/// Hands files made in the core to the shell to save or share
+#[derive(Capability)]
+pub struct Share<Ev> {
+    context: CapabilityContext<ShareOperation, Ev>,
+}
+
+impl<Ev> Share<Ev>
+where
+    Ev: 'static,
+{
+    pub fn new(context: CapabilityContext<ShareOperation, Ev>) -> Self {
+        Self { context }
+    }
+
+    pub fn recording(&self, wav: Vec<u8>) {
+        let ctx = self.context.clone();
+
+        self.context.spawn(async move {
+            ctx.notify_shell(ShareOperation::Recording(wav)).await;
+        })
+    }
+}
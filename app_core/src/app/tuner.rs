@@ -11,15 +11,26 @@ use serde::{Deserialize, Serialize};
 use crate::{
     geometry::{Line, Rect},
     instrument::{self, layout::MenuPosition},
+    play::{
+        AnalysisConfig, InputLevel, InputMeter, Meter, PeaksData, PlayError, PlayOperationOutput,
+    },
     Navigate, Play,
 };
 
 
+mod auto;
 mod chart;
+mod spectrogram;
+pub use self::auto::{AutoTune, AUTO_TUNE_FRAMES};
 pub use self::chart::{Chart, FFTChartEntry, Pair, TriggerState};
+pub use self::spectrogram::{
+    Spectrogram, SpectrogramGrid, NOMINAL_SAMPLE_RATE, SPECTROGRAM_SECONDS,
+};
 
 pub const MIN_F: f32 = 0.06;
 pub const MAX_F: f32 = 6_000.0;
+/// the room is listened to for this long when calibrating
+pub const CALIBRATION_SECONDS: f64 = 3.0;
 
 pub type TuningValue = (usize, f32, f32);
 
@@ -45,6 +56,20 @@ pub struct Model {
     pub tuning: Option<Vec<TuningValue>>,
     pub state: State,
     pub menu_position: MenuPosition,
+    pub peaks: PeaksData,
+    pub auto_tune: Option<AutoTune>,
+    /// the last auto tune didn't find a value for every pair
+    pub auto_tune_failed: bool,
+    pub analysis: AnalysisConfig,
+    pub spectrogram: Spectrogram,
+    /// of the captured input, once the unit reports it
+    pub sample_rate: Option<f64>,
+    /// ambient noise floor measured in each tuned band
+    pub noise_floor: Option<Vec<(usize, f32)>>,
+    pub calibrating: bool,
+    pub input_meter: InputMeter,
+    /// why listening last failed, until recovered
+    pub error: Option<PlayError>,
 }
 
 impl Model {
@@ -64,7 +89,17 @@ pub struct TunerVM {
     pub range: f64,
     pub fft: Vec<Point2<f64>>,
     pub fft_max: Vec<Point2<f64>>,
+    pub peaks: Vec<Point2<f64>>,
+    pub pitch: Option<f32>,
+    pub auto_tuning: Option<f32>,
+    pub auto_tune_failed: bool,
     pub menu_position: MenuPosition,
+    pub spectrogram: SpectrogramGrid,
+    pub calibrating: bool,
+    /// noise floor of each node in dBFS
+    pub noise_floor: Vec<(usize, f32)>,
+    pub input_meter: Meter,
+    pub error: Option<PlayError>,
 }
 
 impl Eq for TunerVM {}
@@ -79,12 +114,22 @@ pub enum TunerEV {
     SetConfig(instrument::Config),
     Activate(bool),
     FftData(Vec<(f32, f32)>),
-    PlayOpStartProcessing(bool),
-    PlayOpStartCapturing(bool),
-    PlayOpStopProcessing(bool),
-    PlayOpStopCapturing(bool),
-    PlayOpPermission(bool),
-    PlayOpInstall(bool),
+    PeaksData(PeaksData),
+    InputLevel(InputLevel),
+    AutoTune(usize),
+    CancelAutoTune,
+    SetAnalysis(AnalysisConfig),
+    Calibrate,
+    NoiseFloorData(Vec<(usize, f32)>),
+    PlayOpCalibrate(PlayOperationOutput),
+    PlayOpStartProcessing(PlayOperationOutput),
+    PlayOpStartCapturing(PlayOperationOutput),
+    PlayOpStopProcessing(PlayOperationOutput),
+    PlayOpStopCapturing(PlayOperationOutput),
+    PlayOpPermission(PlayOperationOutput),
+    PlayOpInstall(PlayOperationOutput),
+    /// clears a playback error and starts listening again
+    Recover,
 }
 
 impl Eq for TunerEV {}
@@ -140,14 +185,7 @@ impl App for Tuner {
                     if start {
                         caps.play.play(TunerEV::PlayOpStartProcessing);
                     } else {
-                        let pairs = self.get_pairs(model);
-                        let values = pairs
-                            .iter()
-                            .map(|p| {
-                                let val = p.value.unwrap_or((0.0, 0.0));
-                                (p.f_n, val.0, val.1)
-                            })
-                            .collect::<Vec<TuningValue>>();
+                        let values = self.get_values(model);
                         model.tuning = Some(values.clone());
                         caps.play.stop_capture_fft(TunerEV::PlayOpStopCapturing);
                         // caps.key_value.write(
@@ -163,6 +201,15 @@ impl App for Tuner {
                 }
             }
             TunerEV::FftData(data) => {
+                let mut auto_tuned = None;
+                if let Some(auto) = model.auto_tune.as_mut() {
+                    if auto.push(data.as_slice()) {
+                        auto_tuned = Some(auto.pick(&model.config));
+                    }
+                }
+
+                model.spectrogram.push(data.as_slice());
+
                 {
                     let mut world = model.world.lock().expect("world lock");
                     model.chart.as_mut().expect("chart").set_fft_data(
@@ -171,60 +218,125 @@ impl App for Tuner {
                         &model.config,
                     );
                 }
+
+                match auto_tuned {
+                    Some(Some(values)) => {
+                        log::info!("auto tune complete");
+                        model.auto_tune = None;
+                        if model.state >= State::SetupComplete {
+                            for value in values.iter() {
+                                caps.play.tune_node(*value);
+                            }
+                        }
+                        model.tuning = Some(values);
+                        self.update_pairs_from_values(model);
+                    }
+                    // a partial pick would leave the other pairs out of order, keep the tuning
+                    Some(None) => {
+                        model.auto_tune = None;
+                        model.auto_tune_failed = true;
+                    }
+                    None => {}
+                }
+
                 caps.render.render();
             }
-            TunerEV::PlayOpPermission(grant) => {
-                if grant {
-                    caps.play.install_au(TunerEV::PlayOpInstall);
-                } else {
-                    model.state = State::None;
-                    caps.navigate.to(crate::Activity::Intro);
-                }
+            TunerEV::AutoTune(frames) => {
+                log::info!("auto tune over {frames} frames");
+                model.auto_tune = Some(AutoTune::new(frames));
+                model.auto_tune_failed = false;
+                caps.render.render();
             }
-            TunerEV::PlayOpInstall(success) => {
-                if !success {
-                    log::error!("tuner play op failed");
-                    caps.navigate.to(crate::Activity::Intro);
-                    model.state = State::None;
-                } else {
-                    model.state = State::SetupComplete;
-                    self.update(TunerEV::Activate(true), model, caps);
-                }
+            TunerEV::CancelAutoTune => {
+                model.auto_tune = None;
+                caps.render.render();
             }
-            TunerEV::PlayOpStartProcessing(success) => {
-                if !success {
-                    log::error!("tuner play op failed");
-                    caps.navigate.to(crate::Activity::Intro);
-                    model.state = State::None;
-                } else {
-                    caps.play.capture_fft(TunerEV::PlayOpStartCapturing)
+            TunerEV::SetAnalysis(analysis) => {
+                model.analysis = analysis;
+                self.reset_spectrogram(model);
+                if model.state == State::Capturing {
+                    caps.play
+                        .capture_fft(model.analysis, TunerEV::PlayOpStartCapturing);
                 }
             }
-            TunerEV::PlayOpStartCapturing(success) => {
-                if !success {
-                    log::error!("tuner play op failed");
-                    caps.navigate.to(crate::Activity::Intro);
-                    model.state = State::None;
+            TunerEV::Calibrate => {
+                if model.state == State::Capturing && !model.calibrating {
+                    // bands are measured only where a pair has been tuned
+                    let values = self
+                        .get_pairs(model)
+                        .iter()
+                        .filter_map(|p| p.value.map(|(freq, amp)| (p.f_n, freq, amp)))
+                        .collect::<Vec<_>>();
+                    caps.play
+                        .calibrate(&values, CALIBRATION_SECONDS, TunerEV::PlayOpCalibrate);
+                    model.calibrating = true;
+                    caps.render.render();
                 } else {
-                    model.state = State::Capturing;
+                    log::warn!("not listening to calibrate");
                 }
             }
-            TunerEV::PlayOpStopProcessing(success) => {
-                if !success {
-                    log::error!("tuner play op failed");
-                    caps.navigate.to(crate::Activity::Intro);
-                    model.state = State::None;
-                } else {
-                    log::info!("done capturing");
+            TunerEV::PlayOpCalibrate(calibrating) => {
+                if let Some(e) = calibrating.error() {
+                    log::error!("calibration not started: {e}");
+                    model.calibrating = false;
+                    caps.render.render();
                 }
             }
-            TunerEV::PlayOpStopCapturing(success) => {
-                if !success {
-                    log::error!("tuner play op failed");
-                    caps.navigate.to(crate::Activity::Intro);
-                    model.state = State::None;
-                } else {
-                    caps.play.pause(TunerEV::PlayOpStopProcessing)
+            TunerEV::NoiseFloorData(floor) => {
+                log::info!("calibrated {} bands", floor.len());
+                model.calibrating = false;
+                model.noise_floor = Some(floor);
+                caps.render.render();
+            }
+            TunerEV::PeaksData(data) => {
+                model.peaks = data;
+                caps.render.render();
+            }
+            TunerEV::InputLevel(level) => {
+                model.input_meter.push(level);
+                caps.render.render();
+            }
+            TunerEV::PlayOpPermission(grant) => match grant.error() {
+                None => caps.play.install_au(TunerEV::PlayOpInstall),
+                Some(e) => self.fail(model, e, caps),
+            },
+            TunerEV::PlayOpInstall(done) => match done.error() {
+                None => {
+                    model.state = State::SetupComplete;
+                    self.update(TunerEV::Activate(true), model, caps);
+                }
+                Some(e) => self.fail(model, e, caps),
+            },
+            TunerEV::PlayOpStartProcessing(done) => match done.error() {
+                None => caps
+                    .play
+                    .capture_fft(model.analysis, TunerEV::PlayOpStartCapturing),
+                Some(e) => self.fail(model, e, caps),
+            },
+            TunerEV::PlayOpStartCapturing(done) => match done {
+                PlayOperationOutput::Failure(e) => self.fail(model, e, caps),
+                done => {
+                    if let PlayOperationOutput::Capturing(sample_rate) = done {
+                        if model.sample_rate != Some(sample_rate) {
+                            _ = model.sample_rate.insert(sample_rate);
+                            self.reset_spectrogram(model);
+                        }
+                    }
+                    model.state = State::Capturing;
+                }
+            },
+            TunerEV::PlayOpStopProcessing(done) => match done.error() {
+                None => log::info!("done capturing"),
+                Some(e) => self.fail(model, e, caps),
+            },
+            TunerEV::PlayOpStopCapturing(done) => match done.error() {
+                None => caps.play.pause(TunerEV::PlayOpStopProcessing),
+                Some(e) => self.fail(model, e, caps),
+            },
+            TunerEV::Recover => {
+                if model.error.take().is_some() {
+                    self.update(TunerEV::Activate(true), model, caps);
+                    caps.render.render();
                 }
             }
             TunerEV::ActivationXY((x, y), id) => {
@@ -288,12 +400,17 @@ impl App for Tuner {
                         .flatten();
 
                     if let Some(f_n) = f_n {
-                        model.chart.as_ref().unwrap().update_value_from_pos(
+                        let value = model.chart.as_ref().unwrap().update_value_from_pos(
                             &mut world,
                             f_n,
                             (&x, &y),
                             &model.config,
                         );
+
+                        if let Some(value) = value.filter(|_| model.state >= State::SetupComplete)
+                        {
+                            caps.play.tune_node(value);
+                        }
                     };
                 }
                 caps.render.render();
@@ -320,12 +437,49 @@ impl App for Tuner {
             range: model.config.height,
             fft,
             fft_max,
+            peaks: model
+                .peaks
+                .peaks
+                .iter()
+                .map(|(freq, magnitude, _)| Chart::value_point(*freq, *magnitude, &model.config))
+                .collect(),
+            pitch: model.peaks.pitch.map(|(freq, _)| freq),
+            auto_tuning: model.auto_tune.as_ref().map(|auto| auto.progress()),
+            auto_tune_failed: model.auto_tune_failed,
             menu_position: model.menu_position.clone(),
+            spectrogram: model.spectrogram.grid(),
+            calibrating: model.calibrating,
+            error: model.error.clone(),
+            noise_floor: model
+                .noise_floor
+                .iter()
+                .flatten()
+                .map(|(f_n, rms)| (*f_n, 20.0 * rms.log10()))
+                .collect(),
+            input_meter: model.input_meter.meter(),
         }
     }
 }
 
 impl Tuner {
+    /// Starts the history over for the current analysis and input rate
+    fn reset_spectrogram(&self, model: &mut Model) {
+        model.spectrogram = Spectrogram::new(
+            SPECTROGRAM_SECONDS,
+            &model.analysis,
+            model.sample_rate.unwrap_or(NOMINAL_SAMPLE_RATE),
+        );
+    }
+
+    /// Stops listening and keeps the cause for the view to offer recovery
+    fn fail(&self, model: &mut Model, error: PlayError, caps: &TunerCapabilities) {
+        log::error!("tuner play op failed: {error}");
+        model.state = State::None;
+        model.calibrating = false;
+        _ = model.error.insert(error);
+        caps.render.render();
+    }
+
     fn update_pairs_from_values(&self, model: &mut Model) {
         if let Some((chart, values)) = model.chart.as_mut().zip(model.tuning.as_ref()) {
             let mut world = model.world.lock().expect("world lock");
@@ -354,6 +508,16 @@ impl Tuner {
             .unwrap_or_default()
     }
 
+    fn get_values(&self, model: &Model) -> Vec<TuningValue> {
+        self.get_pairs(model)
+            .iter()
+            .map(|p| {
+                let val = p.value.unwrap_or((0.0, 0.0));
+                (p.f_n, val.0, val.1)
+            })
+            .collect()
+    }
+
     fn get_fft(&self, model: &Model) -> Vec<(Point2<f64>, Point2<f64>)> {
         model
             .chart
@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+
+use crate::instrument::Config;
+
+use super::{Chart, TuningValue};
+
+pub const AUTO_TUNE_FRAMES: usize = 64;
+const AMP_RANGE: (f32, f32) = (0.01, 0.99);
+
+/// Gathers spectra over a window of frames and picks tuning values from them
+#[derive(Default, Clone, Debug)]
+pub struct AutoTune {
+    pub frames: usize,
+    pub gathered: usize,
+    spectrum: Vec<(f32, f32)>,
+}
+
+impl AutoTune {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: frames.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Averages in another spectrum, returns true once the window is complete
+    pub fn push(&mut self, data: &[(f32, f32)]) -> bool {
+        if self.spectrum.len() != data.len() {
+            if self.gathered > 0 {
+                log::warn!("spectrum size changed, restarting auto tune window");
+            }
+            self.spectrum = data.iter().map(|(freq, _)| (*freq, 0.0)).collect();
+            self.gathered = 0;
+        }
+
+        self.gathered += 1;
+        let n = self.gathered as f32;
+        for ((_, avg), (_, value)) in self.spectrum.iter_mut().zip(data) {
+            *avg += (value - *avg) / n;
+        }
+
+        self.gathered >= self.frames
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.gathered as f32 / self.frames as f32
+    }
+
+    /// Most prominent frequencies that keep pairs a button apart and inside the safe area,
+    /// ordered by frequency so that `f_n` grows with it, `None` unless every pair gets one
+    pub fn pick(&self, config: &Config) -> Option<Vec<TuningValue>> {
+        let mut candidates = self.spectrum.clone();
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+        let mut picked: Vec<(f32, f32, f64)> = vec![];
+
+        for (freq, value) in candidates {
+            if picked.len() >= config.n_buttons {
+                break;
+            }
+
+            let amp = value.clamp(AMP_RANGE.0, AMP_RANGE.1);
+            let x = Chart::value_point(freq, amp, config).x;
+
+            if x <= config.safe_area[0] || x >= config.width - config.safe_area[2] {
+                continue;
+            }
+
+            if picked
+                .iter()
+                .all(|(_, _, p_x)| (p_x - x).abs() > config.button_size)
+            {
+                picked.push((freq, amp, x));
+            }
+        }
+
+        if picked.len() < config.n_buttons {
+            log::warn!(
+                "auto tune found {} of {} values",
+                picked.len(),
+                config.n_buttons
+            );
+            return None;
+        }
+
+        picked.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        Some(
+            picked
+                .into_iter()
+                .enumerate()
+                .map(|(i, (freq, amp, _))| (i + 1, freq, amp))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum(peaks: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        (1..560)
+            .map(|i| {
+                let freq = i as f32 * 10.7;
+                let value = peaks.iter().fold(0.005, |acc, (f, v)| {
+                    let d = (freq - f) / 20.0;
+                    acc + v * (-d * d).exp()
+                });
+                (freq, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn completes_after_window() {
+        let mut auto = AutoTune::new(3);
+        let data = spectrum(&[]);
+
+        assert!(!auto.push(&data));
+        assert!(!auto.push(&data));
+        assert!(auto.push(&data));
+        assert_eq!(auto.progress(), 1.0);
+    }
+
+    #[test]
+    fn picks_ordered_and_spaced_values() {
+        let config = Config::new(2436.0, 1125.0, 458.0, [50.0, 20.0, 10.0, 25.0]);
+        let peaks = (0..config.n_buttons * 2)
+            .map(|i| (150.0 + i as f32 * 250.0, 0.2 + (i % 3) as f32 * 0.2))
+            .collect::<Vec<_>>();
+
+        let mut auto = AutoTune::new(2);
+        auto.push(&spectrum(&peaks));
+        auto.push(&spectrum(&peaks));
+
+        let values = auto.pick(&config).expect("values");
+
+        assert_eq!(values.len(), config.n_buttons);
+        for (i, w) in values.windows(2).enumerate() {
+            assert_eq!(w[0].0, i + 1);
+            assert!(w[0].1 < w[1].1);
+            let x0 = Chart::value_point(w[0].1, w[0].2, &config).x;
+            let x1 = Chart::value_point(w[1].1, w[1].2, &config).x;
+            assert!((x0 - x1).abs() > config.button_size);
+        }
+    }
+
+    #[test]
+    fn picks_nothing_from_too_few_peaks() {
+        let config = Config::new(2436.0, 1125.0, 458.0, [50.0, 20.0, 10.0, 25.0]);
+        let peaks = (0..config.n_buttons / 2)
+            .map(|i| (150.0 + i as f32 * 250.0, 0.6))
+            .collect::<Vec<_>>();
+
+        let mut auto = AutoTune::new(1);
+        auto.push(&spectrum(&peaks));
+
+        assert_eq!(auto.pick(&config), None);
+    }
+}
@@ -150,6 +150,14 @@ impl Chart {
 
     pub fn set_fft_data(&mut self, world: &mut World, data: Vec<(f32, f32)>, config: &Config) {
         let total = data.len();
+
+        // a new fft size or binning lays the entries out afresh
+        if self.fft_values.len() != total {
+            for e in self.fft_values.drain(..) {
+                world.despawn(e).expect("delete fft");
+            }
+        }
+
         for (i, (freq, value)) in data.clone().into_iter().enumerate() {
             if let Some(e) = self.fft_values.get(i) {
                 let mut entry = world.get::<&mut FFTChartEntry>(*e).expect("entry");
@@ -196,10 +204,8 @@ impl Chart {
         values: &[TuningValue],
         config: &Config,
     ) {
-        let range_width = MAX_F - MIN_F;
         for (f_n, value_freq, value_amp) in values {
-            let x = config.width - ((*value_freq - MIN_F) / range_width) as f64 * config.width;
-            let pt = FFTChartEntry::value_point(x, config, *value_amp);
+            let pt = Self::value_point(*value_freq, *value_amp, config);
             if let Some((_, pair)) = world
                 .query_mut::<&mut Pair>()
                 .into_iter()
@@ -215,13 +221,19 @@ impl Chart {
         }
     }
 
+    pub fn value_point(freq: f32, amp: f32, config: &Config) -> Point2<f64> {
+        let range_width = MAX_F - MIN_F;
+        let x = config.width - ((freq - MIN_F) / range_width) as f64 * config.width;
+        FFTChartEntry::value_point(x, config, amp)
+    }
+
     pub fn update_value_from_pos(
         &self,
         world: &mut World,
         f_n: usize,
         (x, y): (&f64, &f64),
         config: &Config,
-    ) {
+    ) -> Option<TuningValue> {
         let range_width = MAX_F - MIN_F;
         let value_freq = MAX_F - (x / config.width) as f32 * range_width + MIN_F;
         let value_amp = FFTChartEntry::point_amp(*y, config);
@@ -261,6 +273,40 @@ impl Chart {
             pair.value = Some((value_freq, value_amp));
             pair.rect.move_x(*x);
             pair.rect.move_y(*y);
+            Some((f_n, value_freq, value_amp))
+        } else {
+            None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_entries_again_when_the_bins_change() {
+        let mut world = World::new();
+        let config = Config {
+            width: 800.0,
+            height: 400.0,
+            n_buttons: 4,
+            ..Default::default()
+        };
+        let mut chart = Chart::new(&mut world, &config);
+        let xs = |chart: &Chart, world: &World| {
+            chart
+                .fft_values
+                .iter()
+                .map(|e| world.get::<&FFTChartEntry>(*e).expect("entry").pt_max.0.x)
+                .collect::<Vec<_>>()
+        };
+
+        chart.set_fft_data(&mut world, vec![(100.0, 0.5); 4], &config);
+        assert_eq!(xs(&chart, &world), vec![800.0, 600.0, 400.0, 200.0]);
+
+        chart.set_fft_data(&mut world, vec![(100.0, 0.5); 2], &config);
+        assert_eq!(xs(&chart, &world), vec![800.0, 400.0]);
+        assert_eq!(world.query::<&FFTChartEntry>().iter().count(), 2);
+    }
+}
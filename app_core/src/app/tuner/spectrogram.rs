@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::MAX_F;
+use crate::play::AnalysisConfig;
+
+pub const SPECTROGRAM_SECONDS: f64 = 8.0;
+pub const SPECTROGRAM_ROWS: usize = 64;
+/// lowest row, below it there's nothing to tune to
+pub const SPECTROGRAM_MIN_F: f32 = 20.0;
+/// intensity range below the loudest recent bin
+const DB_RANGE: f32 = 60.0;
+/// per frame fall of the loudest level
+const CEILING_DECAY: f32 = 0.999;
+/// assumed input rate until the unit reports the device's
+pub const NOMINAL_SAMPLE_RATE: f64 = 44100.0;
+/// most frames kept, the grid goes into the view model on every frame
+const MAX_COLUMNS: usize = 512;
+
+/// Intensities of the last frames, oldest first,
+/// each frame `rows` long from `min_f` to `max_f` on a log scale
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct SpectrogramGrid {
+    pub rows: usize,
+    pub columns: usize,
+    pub min_f: f32,
+    pub max_f: f32,
+    pub data: Vec<u8>,
+}
+
+impl Eq for SpectrogramGrid {}
+
+/// Rolling history of spectra, quantised onto log-spaced rows
+#[derive(Clone, Debug)]
+pub struct Spectrogram {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+    ceiling: f32,
+}
+
+impl Default for Spectrogram {
+    fn default() -> Self {
+        Self::new(
+            SPECTROGRAM_SECONDS,
+            &AnalysisConfig::default(),
+            NOMINAL_SAMPLE_RATE,
+        )
+    }
+}
+
+impl Spectrogram {
+    /// Keeps about `seconds` of frames of input at `sample_rate` analysed with `analysis`,
+    /// fewer when that would be more than [`MAX_COLUMNS`]
+    pub fn new(seconds: f64, analysis: &AnalysisConfig, sample_rate: f64) -> Self {
+        let frames_per_second = sample_rate / analysis.sanitised().hop as f64;
+        let capacity = ((seconds * frames_per_second).ceil().max(1.0) as usize).min(MAX_COLUMNS);
+
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            ceiling: f32::EPSILON,
+        }
+    }
+
+    pub fn push(&mut self, spectrum: &[(f32, f32)]) {
+        let rows = rows(spectrum);
+
+        let loudest = rows.iter().copied().fold(0.0, f32::max);
+        self.ceiling = (self.ceiling * CEILING_DECAY)
+            .max(loudest)
+            .max(f32::EPSILON);
+
+        let frame = rows
+            .into_iter()
+            .map(|m| {
+                let db = 20.0 * (m.max(f32::EPSILON) / self.ceiling).log10();
+                (((db + DB_RANGE) / DB_RANGE).clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+            })
+            .collect();
+
+        if self.frames.len() >= self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn grid(&self) -> SpectrogramGrid {
+        SpectrogramGrid {
+            rows: SPECTROGRAM_ROWS,
+            columns: self.frames.len(),
+            min_f: SPECTROGRAM_MIN_F,
+            max_f: MAX_F,
+            data: self.frames.iter().flatten().copied().collect(),
+        }
+    }
+}
+
+/// Loudest bin within each row's band, or the nearest bin when a band falls between bins
+fn rows(spectrum: &[(f32, f32)]) -> Vec<f32> {
+    let ratio = (MAX_F / SPECTROGRAM_MIN_F).powf(1.0 / SPECTROGRAM_ROWS as f32);
+
+    (0..SPECTROGRAM_ROWS)
+        .map(|r| {
+            let lo = SPECTROGRAM_MIN_F * ratio.powi(r as i32);
+            let hi = lo * ratio;
+            let centre = (lo * hi).sqrt();
+
+            spectrum
+                .iter()
+                .filter(|(f, _)| *f >= lo && *f < hi)
+                .map(|(_, m)| *m)
+                .reduce(f32::max)
+                .or_else(|| {
+                    spectrum
+                        .iter()
+                        .min_by(|a, b| {
+                            (a.0 - centre)
+                                .abs()
+                                .partial_cmp(&(b.0 - centre).abs())
+                                .unwrap_or(std::cmp::Ordering::Equal)
+                        })
+                        .map(|(_, m)| *m)
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum(peak: f32) -> Vec<(f32, f32)> {
+        (1..560)
+            .map(|i| {
+                let freq = i as f32 * 10.7;
+                let d = (freq - peak) / 20.0;
+                (freq, 0.001 + (-d * d).exp())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rolls_over_the_history() {
+        let analysis = AnalysisConfig {
+            fft_size: 8192,
+            hop: 4410,
+            ..Default::default()
+        };
+        let mut spectrogram = Spectrogram::new(2.0, &analysis, 44100.0);
+
+        for _ in 0..25 {
+            spectrogram.push(&spectrum(440.0));
+        }
+
+        let grid = spectrogram.grid();
+        assert_eq!(grid.columns, 20);
+        assert_eq!(grid.rows, SPECTROGRAM_ROWS);
+        assert_eq!(grid.data.len(), grid.rows * grid.columns);
+    }
+
+    #[test]
+    fn sizes_the_history_as_analysed() {
+        let analysis = AnalysisConfig {
+            fft_size: 4096,
+            hop: 1,
+            ..Default::default()
+        };
+
+        // the hop is at least an eighth of the frame
+        assert_eq!(Spectrogram::new(1.0, &analysis, 48000.0).capacity, 94);
+        assert_eq!(Spectrogram::new(1.0, &analysis, 96000.0).capacity, 188);
+        assert_eq!(
+            Spectrogram::new(SPECTROGRAM_SECONDS, &analysis, 96000.0).capacity,
+            MAX_COLUMNS
+        );
+    }
+
+    #[test]
+    fn quantises_relative_to_the_loudest() {
+        let mut spectrogram = Spectrogram::default();
+        spectrogram.push(&spectrum(1000.0));
+
+        let grid = spectrogram.grid();
+        let loudest = grid
+            .data
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, v)| **v)
+            .map(|(row, v)| (row, *v))
+            .unwrap();
+
+        assert_eq!(loudest.1, u8::MAX);
+        let ratio = (MAX_F / SPECTROGRAM_MIN_F).powf(1.0 / SPECTROGRAM_ROWS as f32);
+        let freq = SPECTROGRAM_MIN_F * ratio.powf(loudest.0 as f32 + 0.5);
+        assert!((freq / 1000.0).log2().abs() < 0.25, "{freq}");
+        assert!(grid.data.iter().any(|v| *v < u8::MAX / 4));
+    }
+}
@@ -0,0 +1,292 @@
+use std::f32::consts::TAU;
+
+use app_core::{
+    play::{AnalysisConfig, Binning, Window},
+    tuner::{MAX_F, MIN_F},
+};
+use spectrum_analyzer::{samples_fft_to_spectrum, scaling::divide_by_N_sqrt, FrequencyLimit};
+
+/// centre of the 1/3-octave band series
+const THIRD_OCTAVE_REF: f32 = 1000.0;
+
+/// Windowed FFT over a sliding buffer of the input
+pub struct Analyzer {
+    pub config: AnalysisConfig,
+    window: Vec<f32>,
+    buffer: Vec<f32>,
+}
+
+impl Analyzer {
+    pub fn new(config: AnalysisConfig) -> Self {
+        let config = config.sanitised();
+
+        Self {
+            window: window(config.window, config.fft_size),
+            buffer: Vec::with_capacity(config.fft_size * 2),
+            config,
+        }
+    }
+
+    /// Takes in samples and returns the linear spectrum of every frame they complete,
+    /// keeping whatever is left for the following frames
+    pub fn push(&mut self, samples: &[f32], sample_rate: f64) -> Vec<Vec<(f32, f32)>> {
+        self.buffer.extend_from_slice(samples);
+
+        let mut spectra = vec![];
+
+        while self.buffer.len() >= self.config.fft_size {
+            let frame = self
+                .buffer
+                .iter()
+                .zip(self.window.iter())
+                .map(|(s, w)| s * w)
+                .collect::<Vec<_>>();
+
+            match samples_fft_to_spectrum(
+                frame.as_slice(),
+                sample_rate as u32,
+                FrequencyLimit::Range(MIN_F, MAX_F),
+                Some(&divide_by_N_sqrt),
+            ) {
+                Ok(spectrum) => spectra.push(
+                    spectrum
+                        .data()
+                        .iter()
+                        .map(|(freq, value)| (freq.val(), value.val()))
+                        .collect(),
+                ),
+                Err(e) => log::error!("spectrum: {e:?}"),
+            }
+
+            self.buffer.drain(..self.config.hop);
+        }
+
+        spectra
+    }
+
+    /// The spectrum in the configured bins
+    pub fn bin(&self, spectrum: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        match self.config.binning {
+            Binning::Linear => spectrum.to_vec(),
+            Binning::Log(bins) => {
+                // a band narrower than an FFT bin would only repeat it,
+                // so the scale starts at the first bin and narrow bands merge into the next
+                let resolution = resolution(spectrum);
+                let lowest = resolution.max(MIN_F);
+                let ratio = (MAX_F / lowest).powf(1.0 / bins.max(1) as f32);
+
+                let mut bands = vec![];
+                let mut lo = lowest;
+                for i in 1..=bins {
+                    let hi = lowest * ratio.powi(i as i32);
+                    if hi - lo >= resolution || i == bins {
+                        bands.push((lo, hi));
+                        lo = hi;
+                    }
+                }
+
+                bands_of(spectrum, bands.as_slice())
+            }
+            Binning::ThirdOctave => {
+                let edge = 2_f32.powf(1.0 / 6.0);
+                let bands = (-60..=60)
+                    .map(|k| THIRD_OCTAVE_REF * 2_f32.powf(k as f32 / 3.0))
+                    .filter(|centre| *centre >= MIN_F && *centre <= MAX_F)
+                    .map(|centre| (centre / edge, centre * edge))
+                    .collect::<Vec<_>>();
+
+                bands_of(spectrum, bands.as_slice())
+            }
+        }
+    }
+}
+
+/// Spacing of the spectrum's bins
+fn resolution(spectrum: &[(f32, f32)]) -> f32 {
+    spectrum
+        .windows(2)
+        .next()
+        .map_or(0.0, |pair| pair[1].0 - pair[0].0)
+}
+
+/// Magnitude of each `(lo, hi)` band at its geometric centre,
+/// summing the power of the bins within it or taking the nearest bin for narrow bands
+fn bands_of(spectrum: &[(f32, f32)], bands: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    if spectrum.is_empty() {
+        return vec![];
+    }
+
+    bands
+        .iter()
+        .map(|(lo, hi)| {
+            let centre = (lo * hi).sqrt();
+            let power = spectrum
+                .iter()
+                .filter(|(f, _)| f >= lo && f < hi)
+                .map(|(_, m)| m * m)
+                .sum::<f32>();
+
+            let magnitude = if power > 0.0 {
+                power.sqrt()
+            } else {
+                spectrum
+                    .iter()
+                    .min_by(|a, b| {
+                        (a.0 - centre)
+                            .abs()
+                            .partial_cmp(&(b.0 - centre).abs())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .map(|(_, m)| *m)
+                    .unwrap_or_default()
+            };
+
+            (centre, magnitude)
+        })
+        .collect()
+}
+
+/// Window coefficients, symmetric over `size` samples
+pub fn window(kind: Window, size: usize) -> Vec<f32> {
+    let coefficients: &[f32] = match kind {
+        Window::Hann => &[0.5, 0.5],
+        Window::Hamming => &[0.54, 0.46],
+        Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        Window::FlatTop => &[
+            0.215_578_95,
+            0.416_631_58,
+            0.277_263_16,
+            0.083_578_95,
+            0.006_947_368,
+        ],
+    };
+    let span = (size.max(2) - 1) as f32;
+
+    (0..size)
+        .map(|n| {
+            let x = TAU * n as f32 / span;
+            coefficients
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * a * (k as f32 * x).cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn sine(freq: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * freq * TAU / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn slides_without_losing_samples() {
+        let mut analyzer = Analyzer::new(AnalysisConfig {
+            fft_size: 1024,
+            hop: 256,
+            ..Default::default()
+        });
+
+        let input = sine(440.0, 4096);
+        let frames = input
+            .chunks(300)
+            .map(|block| analyzer.push(block, SAMPLE_RATE).len())
+            .sum::<usize>();
+
+        // every hop after the first full frame
+        assert_eq!(frames, (4096 - 1024) / 256 + 1);
+        assert_eq!(analyzer.buffer.len(), 1024 - 256);
+    }
+
+    #[test]
+    fn sanitises_sizes() {
+        let analyzer = Analyzer::new(AnalysisConfig {
+            fft_size: 3000,
+            hop: 0,
+            ..Default::default()
+        });
+
+        assert_eq!(analyzer.config.fft_size, 4096);
+        assert_eq!(analyzer.config.hop, 512);
+
+        let analyzer = Analyzer::new(AnalysisConfig {
+            fft_size: 100,
+            hop: 10_000,
+            ..Default::default()
+        });
+
+        assert_eq!(analyzer.config.fft_size, 256);
+        assert_eq!(analyzer.config.hop, 256);
+    }
+
+    #[test]
+    fn windows_peak_in_the_middle() {
+        for kind in [
+            Window::Hann,
+            Window::Hamming,
+            Window::BlackmanHarris,
+            Window::FlatTop,
+        ] {
+            let w = window(kind, 513);
+            assert!((w[256] - 1.0).abs() < 1e-3, "{kind:?}: {}", w[256]);
+            assert!(w[0].abs() < 0.1, "{kind:?}: {}", w[0]);
+            assert!((w[100] - w[412]).abs() < 1e-4, "{kind:?} symmetric");
+        }
+    }
+
+    #[test]
+    fn bins_spectra() {
+        for binning in [Binning::Log(32), Binning::ThirdOctave] {
+            let mut analyzer = Analyzer::new(AnalysisConfig {
+                binning,
+                ..Default::default()
+            });
+            let spectrum = analyzer.push(&sine(1000.0, 4096), SAMPLE_RATE).remove(0);
+            let bins = analyzer.bin(spectrum.as_slice());
+
+            assert!(bins.windows(2).all(|w| w[0].0 < w[1].0), "{binning:?}");
+            let loudest = bins
+                .iter()
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .unwrap();
+            assert!(
+                (loudest.0 / 1000.0).log2().abs() < 0.5,
+                "{binning:?}: {loudest:?}"
+            );
+        }
+
+        let analyzer = Analyzer::new(AnalysisConfig::default());
+        let spectrum = vec![(1.0, 1.0), (2.0, 2.0)];
+        assert_eq!(analyzer.bin(spectrum.as_slice()), spectrum);
+    }
+
+    #[test]
+    fn log_bins_are_no_narrower_than_the_fft() {
+        let analyzer = Analyzer::new(AnalysisConfig {
+            binning: Binning::Log(64),
+            ..Default::default()
+        });
+        let resolution = SAMPLE_RATE as f32 / analyzer.config.fft_size as f32;
+        // every FFT bin tells itself apart
+        let spectrum = (1..=(MAX_F / resolution) as usize)
+            .map(|k| (k as f32 * resolution, k as f32))
+            .collect::<Vec<_>>();
+
+        let bins = analyzer.bin(spectrum.as_slice());
+
+        assert!(!bins.is_empty());
+        assert!(bins[0].0 >= resolution);
+        // neighbours repeating a value would be narrow bands falling back on the same bin
+        assert!(bins.windows(2).all(|w| w[0].1 != w[1].1), "{bins:?}");
+    }
+}
@@ -1,26 +1,33 @@
+use std::sync::Arc;
+
 use app_core::{
     instrument::{Config, Node},
-    play::PlayOperation,
-    tuner::{TuningValue, MAX_F, MIN_F},
+    midi::smf,
+    play::{FxChain, PlayError, PlayOperation, Transport},
+    tuner::TuningValue,
 };
 use crux_core::render::Render;
 pub use crux_core::App;
 use crux_macros::Effect;
 use fundsp::hacker32::*;
 use serde::{Deserialize, Serialize};
-use spectrum_analyzer::{
-    samples_fft_to_spectrum,
-    scaling::divide_by_N_sqrt,
-    windows::hann_window,
-    FrequencyLimit,
-};
 
-use crate::{capture::Capture, system::SAMPLE_RATE};
+use crate::{
+    analyzer::Analyzer,
+    calibration::Calibrator,
+    capture::Capture,
+    detector,
+    latency::LatencyProbe,
+    meter::LevelMeter,
+    recorder::Recorder,
+    sequencer::Sequencer,
+    system::{DEFAULT_CHANNELS, DEFAULT_SAMPLE_RATE},
+};
 
 use super::resolve::Resolve;
 use super::system::System;
 
-const ANALYZE_SAMPLES_COUNT: usize = 4096;
+const MAX_PEAKS: usize = 8;
 
 #[derive(Default)]
 pub struct Model {
@@ -28,14 +35,76 @@ pub struct Model {
     config: Config,
     nodes: Vec<Node>,
     tuning: Vec<TuningValue>,
-    audio_data: Vec<Vec<f32>>,
-    analyze_samples: Vec<f32>,
+    /// the latest rendered block, shared with the view until the next is processed
+    audio_data: Arc<Vec<Vec<f32>>>,
+    /// stands in for the input while a MIDI file plays the nodes
+    silence: Vec<f32>,
     frame_size: usize,
-    capturing: bool,
+    analyzer: Option<Analyzer>,
+    sample_rate: Option<f64>,
+    channels: Option<usize>,
+    recorder: Option<Recorder>,
+    effects: FxChain,
+    sequencer: Option<Sequencer>,
+    master_gain: Option<f32>,
+    calibrator: Option<Calibrator>,
+    noise_floor: Vec<(usize, f32)>,
+    meter: LevelMeter,
+    probe: Option<LatencyProbe>,
+    #[cfg(feature = "osc")]
+    osc: Option<crate::osc::OscEndpoint>,
+    #[cfg(feature = "osc")]
+    osc_frames: usize,
+}
+
+impl Model {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE)
+    }
+
+    fn channels(&self) -> usize {
+        self.channels.unwrap_or(DEFAULT_CHANNELS)
+    }
+
+    /// Builds the node graph, failing when the nodes and their tuning don't pair up
+    fn build_system(&mut self) -> Result<(), PlayError> {
+        let sys = System::new(
+            self.nodes.as_slice(),
+            self.tuning.as_slice(),
+            self.channels(),
+            self.sample_rate(),
+            &self.effects,
+        )
+        .map_err(|e| PlayError::GraphConstruction(e.to_string()))?;
+        let sys = self.system.insert(sys);
+
+        if let Some(gain) = self.master_gain {
+            sys.set_master_gain(gain);
+        }
+
+        sys.set_noise_floor(self.noise_floor.as_slice());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "osc")]
+    fn start_osc(&mut self) {
+        if self.osc.is_some() {
+            return;
+        }
+
+        match crate::osc::OscEndpoint::bind(crate::osc::DEFAULT_PORT) {
+            Ok(osc) => {
+                log::info!("osc listening on {}", osc.local_addr());
+                _ = self.osc.insert(osc);
+            }
+            Err(e) => log::error!("start osc: {e:?}"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
-pub struct ViewModel(pub Vec<Vec<f32>>);
+pub struct ViewModel(pub Arc<Vec<Vec<f32>>>);
 
 #[derive(Default)]
 pub struct RedSirenAU;
@@ -62,39 +131,82 @@ impl App for RedSirenAU {
                 model.config = config;
                 model.nodes = nodes;
                 model.tuning = tuning;
-                _ = model.system.insert(System::new(
-                    model.nodes.as_slice(),
-                    &model.config,
-                    model.tuning.as_slice(),
-                ));
 
-                caps.render.render();
-                caps.resolve.resolve_success(true);
+                match model.build_system() {
+                    Ok(_) => {
+                        #[cfg(feature = "osc")]
+                        model.start_osc();
+
+                        caps.render.render();
+                        caps.resolve.resolve_success();
+                    }
+                    Err(e) => {
+                        log::error!("build system: {e}");
+                        model.system = None;
+                        caps.resolve.resolve_failure(e);
+                    }
+                }
+            }
+            PlayOperation::SampleRate(sample_rate) => {
+                log::info!("sample rate: {sample_rate}");
+                _ = model.sample_rate.insert(sample_rate);
+
+                if let Some(seq) = model.sequencer.as_mut() {
+                    seq.set_sample_rate(sample_rate);
+                }
+
+                if model.system.is_some() {
+                    if let Err(e) = model.build_system() {
+                        log::error!("rebuild system: {e}");
+                    }
+                }
+            }
+            PlayOperation::OutputChannels(channels) => {
+                log::info!("output channels: {channels}");
+                _ = model.channels.insert(channels);
+
+                if model.system.is_some() {
+                    if let Err(e) = model.build_system() {
+                        log::error!("rebuild system: {e}");
+                    }
+                }
             }
             PlayOperation::Input(input) => {
-                if model.capturing {
-                    let data = input.first().cloned().unwrap_or(vec![]);
-                    if model.analyze_samples.len() < ANALYZE_SAMPLES_COUNT {
-                        model.analyze_samples.extend(data)
-                    } else {
-                        let samples = std::mem::replace(&mut model.analyze_samples, data);
-
-                        let hann_window = hann_window(samples.as_slice());
-
-                        let spectrum_hann_window = samples_fft_to_spectrum(
-                            &hann_window,
-                            SAMPLE_RATE as u32,
-                            FrequencyLimit::Range(MIN_F, MAX_F),
-                            Some(&divide_by_N_sqrt),
-                        )
-                        .unwrap();
-
-                        caps.capture.capture_fft(Vec::from_iter(
-                            spectrum_hann_window
-                                .data()
-                                .iter()
-                                .map(|(freq, value)| (freq.val(), value.val())),
+                #[cfg(feature = "osc")]
+                while let Some(op) = model.osc.as_ref().and_then(|osc| osc.next_op()) {
+                    self.update(op, model, caps);
+                }
+
+                // raw levels, ahead of any processing
+                let data = input.first().map_or(&[][..], |ch| ch.as_slice());
+                let sample_rate = model.sample_rate();
+                if let Some(level) = model.meter.push(data, sample_rate) {
+                    caps.capture.capture_input_level(level);
+                }
+
+                if let Some(probe) = model.probe.as_mut() {
+                    probe.push(data);
+                }
+
+                if let Some(calibrator) = model.calibrator.as_mut() {
+                    if let Some(floor) = calibrator.push(data) {
+                        log::info!("noise floor: {floor:?}");
+                        model.calibrator = None;
+                        caps.capture.capture_noise_floor(floor.clone());
+                        self.update(PlayOperation::NoiseFloor(floor), model, caps);
+                    }
+                }
+
+                if let Some(analyzer) = model.analyzer.as_mut() {
+                    let data = input.first().map_or(&[][..], |ch| ch.as_slice());
+                    let sample_rate = model.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+
+                    for spectrum in analyzer.push(data, sample_rate) {
+                        caps.capture.capture_peaks(detector::detect(
+                            spectrum.as_slice(),
+                            model.config.n_buttons.min(MAX_PEAKS),
                         ));
+                        caps.capture.capture_fft(analyzer.bin(spectrum.as_slice()));
                     }
                 } else if let Some(sys) = model.system.as_mut() {
                     let frame_size = input.first().map_or(0, |ch| ch.len());
@@ -105,25 +217,60 @@ impl App for RedSirenAU {
                         }
 
                         model.frame_size = frame_size;
-                        model.audio_data = (0..channels)
-                            .map(|_| (0..frame_size).map(|_| 0_f32).collect())
-                            .collect();
+                        model.audio_data = Arc::new(vec![vec![0_f32; frame_size]; channels]);
+                        model.silence = vec![0_f32; frame_size];
                     }
 
-                    let input = input
-                        .iter()
-                        .take(1)
-                        .map(|ch| ch.as_slice())
-                        .collect::<Vec<_>>();
+                    // the view has let go of the last block, so this doesn't copy it
+                    let output = Arc::make_mut(&mut model.audio_data);
+                    let mut data = input.first().map_or(&[][..], |ch| ch.as_slice());
 
-                    let mut output = model
-                        .audio_data
-                        .iter_mut()
-                        .map(|ch| ch.as_mut_slice())
-                        .collect::<Vec<_>>();
+                    let mut midi_finished = false;
+                    if let Some(seq) = model.sequencer.as_mut().filter(|s| s.is_playing()) {
+                        seq.advance_gates(
+                            frame_size,
+                            model.nodes.as_slice(),
+                            |f_n, offset, level| {
+                                sys.set_node_gate(f_n, offset, level);
+                            },
+                        );
+                        midi_finished = seq.is_finished();
+
+                        // the file stands in for the microphone
+                        data = model.silence.as_slice();
+                    }
+
+                    sys.process(model.frame_size, &[data], output.as_mut_slice());
+
+                    if midi_finished {
+                        sys.release_gates();
+                    }
 
-                    sys.net_be
-                        .process(model.frame_size, input.as_slice(), output.as_mut_slice());
+                    if let Some(probe) = model.probe.as_mut() {
+                        probe.emit(output.as_mut_slice());
+
+                        if probe.search() {
+                            let latency = probe.latency();
+                            log::info!("round trip latency: {latency:?}");
+                            model.probe = None;
+                            caps.capture.capture_latency(latency);
+                        }
+                    }
+
+                    if let Some(recorder) = model.recorder.as_mut() {
+                        recorder.push(output.as_slice());
+                    }
+
+                    #[cfg(feature = "osc")]
+                    if let Some(osc) = model.osc.as_ref() {
+                        let interval = (crate::osc::MONITOR_INTERVAL * sys.sample_rate) as usize;
+                        model.osc_frames += frame_size;
+                        if model.osc_frames >= interval {
+                            model.osc_frames = 0;
+                            sys.read_snoops();
+                            osc.broadcast(sys.node_levels(), sys.out_data.as_slice());
+                        }
+                    }
 
                     caps.render.render();
                 } else {
@@ -132,38 +279,192 @@ impl App for RedSirenAU {
             }
             PlayOperation::SendSnoops => {
                 if let Some(sys) = model.system.as_mut() {
-                    if let Some(snp) = sys.out_snp.get() {
-                        let mut data = Vec::new();
-                        for i in 0..snp.size() {
-                            data.push(snp.at(i))
-                        }
-                        caps.capture.capture_data(data);
-                    }
-
-                    let mut datasets = vec![];
-                    for (snp, f_n) in sys
-                        .node_snp
-                        .iter_mut()
-                        .filter_map(|(s, f_n)| s.get().zip(Some(f_n)))
-                    {
-                        let mut data = Vec::new();
-                        for i in 0..snp.size() {
-                            data.push(snp.at(i))
-                        }
-                        datasets.push((*f_n, data));
+                    sys.read_snoops();
+
+                    if !sys.out_data.is_empty() {
+                        caps.capture.capture_data(sys.out_data.clone());
                     }
+
+                    let datasets = sys
+                        .node_data
+                        .iter()
+                        .filter(|(_, data)| !data.is_empty())
+                        .cloned()
+                        .collect::<Vec<_>>();
                     if !datasets.is_empty() {
                         caps.capture.capture_nodes_data(datasets);
                     }
                 }
             }
-            PlayOperation::Capture(capturing) => {
-                model.capturing = capturing;
-                caps.resolve.resolve_success(true);
+            PlayOperation::NodeSensitivity(f_n, freq) => {
+                if let Some(t) = model.tuning.iter_mut().find(|t| t.0 == f_n) {
+                    t.1 = freq;
+                }
+                if !model
+                    .system
+                    .as_ref()
+                    .map_or(false, |sys| sys.set_node_sensitivity(f_n, freq))
+                {
+                    log::warn!("no node {f_n} to sensitise");
+                }
+            }
+            PlayOperation::NodeGain(f_n, amp) => {
+                if let Some(t) = model.tuning.iter_mut().find(|t| t.0 == f_n) {
+                    t.2 = amp;
+                }
+                if !model
+                    .system
+                    .as_mut()
+                    .map_or(false, |sys| sys.set_node_gain(f_n, amp))
+                {
+                    log::warn!("no node {f_n} to set gain");
+                }
+            }
+            PlayOperation::NodeQ(f_n, q) => {
+                if !model
+                    .system
+                    .as_ref()
+                    .map_or(false, |sys| sys.set_node_q(f_n, q))
+                {
+                    log::warn!("no node {f_n} to set q");
+                }
+            }
+            PlayOperation::NodeFreq(f_n, freq) => {
+                if !model
+                    .system
+                    .as_ref()
+                    .map_or(false, |sys| sys.set_node_freq(f_n, freq))
+                {
+                    log::warn!("no node {f_n} to set freq");
+                }
+            }
+            PlayOperation::NodePan(f_n, pan) => {
+                if let Some(node) = model.nodes.iter_mut().find(|n| n.f_n == f_n) {
+                    node.pan = pan;
+                }
+                if !model
+                    .system
+                    .as_ref()
+                    .map_or(false, |sys| sys.set_node_pan(f_n, pan))
+                {
+                    log::warn!("no node {f_n} to pan");
+                }
+            }
+            PlayOperation::MasterGain(gain) => {
+                _ = model.master_gain.insert(gain);
+                if let Some(sys) = model.system.as_ref() {
+                    sys.set_master_gain(gain);
+                }
+            }
+            PlayOperation::Calibrate(tuning, seconds) => {
+                log::info!("calibrating {} bands over {seconds}s", tuning.len());
+                _ = model.calibrator.insert(Calibrator::new(
+                    tuning.as_slice(),
+                    seconds,
+                    model.sample_rate(),
+                ));
+                caps.resolve.resolve_success();
+            }
+            PlayOperation::NoiseFloor(floor) => {
+                if let Some(sys) = model.system.as_mut() {
+                    sys.set_noise_floor(floor.as_slice());
+                }
+                model.noise_floor = floor;
+            }
+            PlayOperation::MeasureLatency => {
+                if model.system.is_some() {
+                    _ = model.probe.insert(LatencyProbe::new(model.sample_rate()));
+                    caps.resolve.resolve_success();
+                } else {
+                    log::warn!("no output to measure latency through");
+                    caps.resolve.resolve_failure(PlayError::Operation(
+                        "no output to measure latency through".to_string(),
+                    ));
+                }
+            }
+            PlayOperation::StartRecording(max_s) => {
+                log::info!("recording up to {max_s}s");
+                _ = model.recorder.insert(Recorder::new(
+                    max_s,
+                    model.sample_rate(),
+                    model.channels(),
+                ));
+                caps.resolve.resolve_success();
+            }
+            PlayOperation::StopRecording => match model.recorder.take() {
+                Some(recorder) => match recorder.finish_detached(model.sample_rate()) {
+                    Ok(encoded) => {
+                        caps.capture.capture_recording(encoded);
+                        caps.resolve.resolve_success();
+                    }
+                    Err(e) => {
+                        log::error!("encode recording: {e:?}");
+                        caps.resolve.resolve_failure(PlayError::Operation(format!(
+                            "encode recording: {e}"
+                        )));
+                    }
+                },
+                None => {
+                    log::warn!("not recording");
+                    caps.resolve
+                        .resolve_failure(PlayError::Operation("not recording".to_string()));
+                }
+            },
+            PlayOperation::Effects(chain) => {
+                if let Some(sys) = model.system.as_mut() {
+                    sys.set_effects(&chain);
+                }
+                model.effects = chain;
+                caps.resolve.resolve_success();
+            }
+            PlayOperation::LoadMidi(bytes) => match smf::read(bytes.as_slice()) {
+                Ok(events) => {
+                    log::info!("loaded {} midi events", events.len());
+                    if let Some(sys) = model.system.as_mut() {
+                        sys.release_gates();
+                    }
+                    _ = model
+                        .sequencer
+                        .insert(Sequencer::new(events, model.sample_rate()));
+                    caps.resolve.resolve_success();
+                }
+                Err(e) => {
+                    log::error!("read midi: {e:?}");
+                    caps.resolve
+                        .resolve_failure(PlayError::Operation(format!("read midi: {e}")));
+                }
+            },
+            PlayOperation::Transport(transport) => match model.sequencer.as_mut() {
+                Some(seq) => {
+                    match transport {
+                        Transport::Play => seq.play(),
+                        Transport::Stop => seq.stop(),
+                        Transport::Seek(time) => seq.seek(time),
+                    }
+                    if transport != Transport::Play {
+                        if let Some(sys) = model.system.as_mut() {
+                            sys.release_gates();
+                        }
+                    }
+                    caps.resolve.resolve_success();
+                }
+                None => {
+                    log::warn!("no midi loaded");
+                    caps.resolve
+                        .resolve_failure(PlayError::Operation("no midi loaded".to_string()));
+                }
+            },
+            PlayOperation::Capture(analysis) => {
+                model.analyzer = analysis.map(Analyzer::new);
+                if model.analyzer.is_some() {
+                    caps.resolve.resolve_capturing(model.sample_rate());
+                } else {
+                    caps.resolve.resolve_success();
+                }
             }
             op => {
                 log::debug!("op: {op:?} reached hard bottom");
-                caps.resolve.resolve_success(true);
+                caps.resolve.resolve_success();
             }
         }
     }
@@ -174,4 +475,75 @@ impl App for RedSirenAU {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use app_core::play::{AnalysisConfig, CaptureOutput, PeaksData, PlayOperationOutput};
+    use crux_core::testing::AppTester;
+    use std::f32::consts::TAU;
+
+    fn peaks(effects: Vec<Effect>) -> Vec<PeaksData> {
+        effects
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Capture(request) => match request.operation {
+                    CaptureOutput::CapturePeaks(peaks) => Some(peaks),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn caps_detected_peaks() {
+        let app = AppTester::<RedSirenAU, Effect>::default();
+        let mut model = Model {
+            config: Config {
+                n_buttons: 24,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        app.update(
+            PlayOperation::Capture(Some(AnalysisConfig::default())),
+            &mut model,
+        );
+
+        // more clear partials than there are peaks to report
+        let input = (0..AnalysisConfig::default().fft_size)
+            .map(|i| {
+                (1..=24)
+                    .map(|k| (TAU * 200.0 * k as f32 * i as f32 / 44100.0).sin())
+                    .sum::<f32>()
+                    / 24.0
+            })
+            .collect::<Vec<_>>();
+        let update = app.update(PlayOperation::Input(Arc::new(vec![input])), &mut model);
+
+        let peaks = peaks(update.effects);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].peaks.len(), MAX_PEAKS);
+    }
+
+    #[test]
+    fn fails_to_build_without_nodes() {
+        let app = AppTester::<RedSirenAU, Effect>::default();
+        let mut model = Model::default();
+
+        let update = app.update(
+            PlayOperation::Config(Config::default(), vec![], vec![]),
+            &mut model,
+        );
+
+        assert!(model.system.is_none());
+        assert!(update.effects.into_iter().any(|effect| matches!(
+            effect,
+            Effect::Resolve(request)
+                if matches!(
+                    request.operation,
+                    PlayOperationOutput::Failure(PlayError::GraphConstruction(_))
+                )
+        )));
+    }
+}
@@ -0,0 +1,22 @@
+use std::{env, fs, io::BufReader};
+
+use anyhow::{anyhow, Result};
+use aucore::render::{render_wav, RenderSetup};
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let usage = || anyhow!("usage: render <setup.json> <input.wav> <output.wav>");
+
+    let setup_path = args.next().ok_or_else(usage)?;
+    let input_path = args.next().ok_or_else(usage)?;
+    let output_path = args.next().ok_or_else(usage)?;
+
+    let setup = serde_json::from_slice::<RenderSetup>(fs::read(setup_path)?.as_slice())?;
+    let input = BufReader::new(fs::File::open(input_path)?);
+
+    let output = render_wav(input, &setup)?;
+
+    fs::write(output_path, output)?;
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use app_core::tuner::TuningValue;
+use fundsp::hacker32::*;
+
+use crate::system::resting_q;
+
+/// filters settle for this long before the floor is measured
+const SETTLE_SECONDS: f64 = 0.1;
+/// quietest floor taken as measured, below it the input is digital silence
+pub const MIN_FLOOR: f32 = 1e-7;
+/// tuning amplitudes span this far above the floor, in dB
+pub const DYNAMIC_RANGE_DB: f32 = 60.0;
+/// a band opens this far above its floor, in dB
+pub const GATE_MARGIN_DB: f32 = 6.0;
+
+/// Measures the ambient noise floor of the input in each tuned band
+pub struct Calibrator {
+    bands: Vec<(usize, Box<dyn AudioUnit32>)>,
+    power: Vec<f64>,
+    settle: usize,
+    remaining: usize,
+    measured: usize,
+}
+
+impl Calibrator {
+    /// Listens for `seconds` through the same band-pass each node applies to the input
+    pub fn new(tuning: &[TuningValue], seconds: f64, sample_rate: f64) -> Self {
+        let q = resting_q(tuning.len());
+        let bands = tuning
+            .iter()
+            .map(|(f_n, freq, _)| {
+                let mut band: Box<dyn AudioUnit32> = Box::new(bandrez_hz(*freq, q));
+                band.set_sample_rate(sample_rate);
+                (*f_n, band)
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            power: vec![0.0; bands.len()],
+            bands,
+            settle: (SETTLE_SECONDS * sample_rate) as usize,
+            remaining: (seconds.max(0.0) * sample_rate).max(1.0) as usize,
+            measured: 0,
+        }
+    }
+
+    /// Takes in samples, returning the RMS of each band once enough have been measured
+    pub fn push(&mut self, samples: &[f32]) -> Option<Vec<(usize, f32)>> {
+        let mut out = [0_f32];
+
+        for sample in samples {
+            if self.remaining == 0 {
+                break;
+            }
+
+            for ((_, band), power) in self.bands.iter_mut().zip(self.power.iter_mut()) {
+                band.tick(&[*sample], &mut out);
+                if self.settle == 0 {
+                    *power += (out[0] as f64).powi(2);
+                }
+            }
+
+            if self.settle > 0 {
+                self.settle -= 1;
+            } else {
+                self.measured += 1;
+                self.remaining -= 1;
+            }
+        }
+
+        (self.remaining == 0).then(|| {
+            self.bands
+                .iter()
+                .zip(self.power.iter())
+                .map(|((f_n, _), power)| {
+                    let rms = (power / self.measured.max(1) as f64).sqrt() as f32;
+                    (*f_n, rms.max(MIN_FLOOR))
+                })
+                .collect()
+        })
+    }
+}
+
+/// Node input gain bringing a band played at tuning amplitude `amp` to full scale,
+/// `amp` spanning `DYNAMIC_RANGE_DB` above the band's `floor`
+pub fn node_gain(amp: f32, floor: f32) -> f32 {
+    1.0 / (floor.max(MIN_FLOOR) * db_amp(amp.clamp(0.0, 1.0) * DYNAMIC_RANGE_DB))
+}
+
+/// Level of the band after `gain` below which the node hears only the room
+pub fn gate_threshold(floor: f32, gain: f32) -> f32 {
+    floor * db_amp(GATE_MARGIN_DB) * gain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    #[test]
+    fn measures_louder_bands_higher() {
+        let tuning = vec![(1, 220.0, 0.5), (2, 1760.0, 0.5)];
+        let mut calibrator = Calibrator::new(tuning.as_slice(), 0.5, SAMPLE_RATE);
+
+        let mut hiss = noise();
+        hiss.set_sample_rate(SAMPLE_RATE);
+        let input = (0..SAMPLE_RATE as usize)
+            .map(|i| {
+                0.01 * hiss.get_mono() + 0.1 * (i as f32 * 1760.0 * TAU / SAMPLE_RATE as f32).sin()
+            })
+            .collect::<Vec<_>>();
+
+        let mut floor = None;
+        for block in input.chunks(512) {
+            if let Some(measured) = calibrator.push(block) {
+                floor = Some(measured);
+                break;
+            }
+        }
+
+        let floor = floor.expect("measured within the input");
+        assert_eq!(
+            floor.iter().map(|(f_n, _)| *f_n).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(floor[0].1 > MIN_FLOOR, "{floor:?}");
+        assert!(floor[1].1 > floor[0].1 * 4.0, "{floor:?}");
+    }
+
+    #[test]
+    fn silence_stays_above_the_minimum() {
+        let mut calibrator = Calibrator::new(&[(1, 440.0, 0.5)], 0.01, SAMPLE_RATE);
+        let floor = calibrator.push(vec![0.0; SAMPLE_RATE as usize].as_slice());
+
+        assert_eq!(floor, Some(vec![(1, MIN_FLOOR)]));
+    }
+
+    #[test]
+    fn derives_gain_and_threshold_from_the_floor() {
+        let quiet = node_gain(0.5, 1e-4);
+        let loud = node_gain(0.5, 1e-2);
+        assert!((quiet / loud - 100.0).abs() < 1e-2);
+        assert!(node_gain(0.0, 1e-4) > node_gain(1.0, 1e-4));
+
+        let floor = 1e-3;
+        let gain = node_gain(0.0, floor);
+        assert!((floor * gain - 1.0).abs() < 1e-4);
+        assert!(gate_threshold(floor, gain) > floor * gain);
+    }
+}
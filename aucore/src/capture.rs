@@ -1,6 +1,7 @@
 use crux_core::capability::CapabilityContext;
 use crux_macros::Capability;
-use app_core::play::CaptureOutput;
+use futures::channel::oneshot;
+use app_core::play::{CaptureOutput, InputLevel, Latency, PeaksData};
 
 
 #[derive(Capability)]
@@ -39,4 +40,48 @@ where
             ctx.notify_shell(CaptureOutput::CaptureNodesData(captured)).await;
         })
     }
+
+    /// Hands over a recording once it's encoded
+    pub fn capture_recording(&self, encoded: oneshot::Receiver<Vec<u8>>) {
+        let ctx = self.context.clone();
+        log::debug!("capture_recording");
+        self.context.spawn(async move {
+            match encoded.await {
+                Ok(captured) => ctx.notify_shell(CaptureOutput::CaptureRecording(captured)).await,
+                Err(_) => log::warn!("recording wasn't encoded"),
+            }
+        })
+    }
+
+    pub fn capture_peaks(&self, captured: PeaksData) {
+        let ctx = self.context.clone();
+        log::debug!("capture_peaks");
+        self.context.spawn(async move {
+            ctx.notify_shell(CaptureOutput::CapturePeaks(captured)).await;
+        })
+    }
+
+    pub fn capture_noise_floor(&self, captured: Vec<(usize, f32)>) {
+        let ctx = self.context.clone();
+        log::debug!("capture_noise_floor");
+        self.context.spawn(async move {
+            ctx.notify_shell(CaptureOutput::CaptureNoiseFloor(captured)).await;
+        })
+    }
+
+    pub fn capture_input_level(&self, captured: InputLevel) {
+        let ctx = self.context.clone();
+        log::trace!("capture_input_level");
+        self.context.spawn(async move {
+            ctx.notify_shell(CaptureOutput::CaptureInputLevel(captured)).await;
+        })
+    }
+
+    pub fn capture_latency(&self, captured: Option<Latency>) {
+        let ctx = self.context.clone();
+        log::debug!("capture_latency");
+        self.context.spawn(async move {
+            ctx.notify_shell(CaptureOutput::CaptureLatency(captured)).await;
+        })
+    }
 }
@@ -0,0 +1,185 @@
+use std::cmp::Ordering;
+
+use app_core::play::PeaksData;
+
+const PEAK_FLOOR_RATIO: f32 = 4.0;
+const PEAK_MIN_DISTANCE: usize = 2;
+const HPS_HARMONICS: usize = 4;
+
+/// Finds dominant frequencies in a linearly binned spectrum
+/// and estimates the fundamental with a harmonic product spectrum
+pub fn detect(spectrum: &[(f32, f32)], max_peaks: usize) -> PeaksData {
+    PeaksData {
+        pitch: harmonic_product_spectrum(spectrum, HPS_HARMONICS),
+        peaks: find_peaks(spectrum, max_peaks),
+    }
+}
+
+/// Local maxima well above the median floor, refined by parabolic interpolation.
+/// Returns `(frequency, magnitude, confidence)`, loudest first.
+pub fn find_peaks(spectrum: &[(f32, f32)], max_peaks: usize) -> Vec<(f32, f32, f32)> {
+    if spectrum.len() < 3 || max_peaks == 0 {
+        return vec![];
+    }
+
+    let floor = median(spectrum.iter().map(|(_, m)| *m));
+    let threshold = (floor * PEAK_FLOOR_RATIO).max(f32::EPSILON);
+    let d_f = bin_width(spectrum);
+
+    let mut candidates = (1..spectrum.len() - 1)
+        .filter(|i| {
+            let (a, b, c) = (spectrum[i - 1].1, spectrum[*i].1, spectrum[i + 1].1);
+            b > threshold && b >= a && b > c
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| cmp_desc(spectrum[*a].1, spectrum[*b].1));
+
+    let mut picked: Vec<usize> = vec![];
+    for i in candidates {
+        if picked.len() >= max_peaks {
+            break;
+        }
+        if picked.iter().all(|p| p.abs_diff(i) > PEAK_MIN_DISTANCE) {
+            picked.push(i);
+        }
+    }
+
+    picked
+        .into_iter()
+        .map(|i| {
+            let (a, b, c) = (spectrum[i - 1].1, spectrum[i].1, spectrum[i + 1].1);
+            let (offset, magnitude) = interpolate(a, b, c);
+            let freq = spectrum[i].0 + offset * d_f;
+            let confidence = (1.0 - floor / magnitude).clamp(0.0, 1.0);
+            (freq, magnitude, confidence)
+        })
+        .collect()
+}
+
+/// Fundamental frequency and confidence, if the spectrum has any energy
+pub fn harmonic_product_spectrum(spectrum: &[(f32, f32)], harmonics: usize) -> Option<(f32, f32)> {
+    let d_f = bin_width(spectrum);
+    if spectrum.len() < 3 || d_f <= 0.0 {
+        return None;
+    }
+
+    let f_0 = spectrum[0].0;
+    let bin_of = |freq: f32| ((freq - f_0) / d_f).round() as usize;
+
+    let hps = spectrum
+        .iter()
+        .map(|(freq, magnitude)| {
+            (2..=harmonics).fold(*magnitude, |acc, h| {
+                spectrum
+                    .get(bin_of(freq * h as f32))
+                    .map_or(0.0, |(_, m)| acc * m)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let (i, max) = hps
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))?;
+
+    if *max <= 0.0 {
+        return None;
+    }
+
+    let mean = hps.iter().sum::<f32>() / hps.len() as f32;
+    let offset = if i > 0 && i + 1 < hps.len() {
+        interpolate(hps[i - 1], hps[i], hps[i + 1]).0
+    } else {
+        0.0
+    };
+
+    Some((spectrum[i].0 + offset * d_f, (1.0 - mean / max).clamp(0.0, 1.0)))
+}
+
+/// Vertex of the parabola through three neighbouring bins, as `(bin offset, magnitude)`
+fn interpolate(a: f32, b: f32, c: f32) -> (f32, f32) {
+    let denom = a - 2.0 * b + c;
+    if denom.abs() < f32::EPSILON {
+        return (0.0, b);
+    }
+    let p = (0.5 * (a - c) / denom).clamp(-0.5, 0.5);
+    (p, b - 0.25 * (a - c) * p)
+}
+
+fn bin_width(spectrum: &[(f32, f32)]) -> f32 {
+    match spectrum {
+        [(f_0, _), (f_1, _), ..] => f_1 - f_0,
+        _ => 0.0,
+    }
+}
+
+fn median(values: impl Iterator<Item = f32>) -> f32 {
+    let mut values = values.collect::<Vec<_>>();
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    values[values.len() / 2]
+}
+
+fn cmp_desc(a: f32, b: f32) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const D_F: f32 = 10.0;
+
+    fn spectrum_with(peaks: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        (1..600)
+            .map(|i| {
+                let freq = i as f32 * D_F;
+                let magnitude = peaks.iter().fold(0.01, |acc, (f, m)| {
+                    let d = (freq - f) / D_F;
+                    acc + m * (-d * d).exp()
+                });
+                (freq, magnitude)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_interpolated_peaks_loudest_first() {
+        let spectrum = spectrum_with(&[(443.0, 1.0), (1207.0, 0.5)]);
+
+        let peaks = find_peaks(&spectrum, 4);
+
+        assert_eq!(peaks.len(), 2);
+        assert!((peaks[0].0 - 443.0).abs() < D_F / 2.0, "{peaks:?}");
+        assert!((peaks[1].0 - 1207.0).abs() < D_F / 2.0, "{peaks:?}");
+        assert!(peaks[0].1 > peaks[1].1);
+        assert!(peaks.iter().all(|p| p.2 > 0.9));
+    }
+
+    #[test]
+    fn limits_peak_count() {
+        let spectrum = spectrum_with(&[(200.0, 1.0), (900.0, 0.8), (2000.0, 0.6)]);
+
+        assert_eq!(find_peaks(&spectrum, 2).len(), 2);
+    }
+
+    #[test]
+    fn no_peaks_in_flat_spectrum() {
+        let spectrum = spectrum_with(&[]);
+
+        assert!(find_peaks(&spectrum, 4).is_empty());
+    }
+
+    #[test]
+    fn estimates_fundamental_of_harmonic_series() {
+        let spectrum = spectrum_with(&[(220.0, 0.6), (440.0, 1.0), (660.0, 0.8), (880.0, 0.5)]);
+
+        let (pitch, confidence) = harmonic_product_spectrum(&spectrum, HPS_HARMONICS).unwrap();
+
+        assert!((pitch - 220.0).abs() < D_F, "{pitch}");
+        assert!(confidence > 0.5);
+    }
+}
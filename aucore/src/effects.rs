@@ -0,0 +1,191 @@
+use app_core::play::{FxChain, FxUnit};
+use fundsp::hacker32::*;
+
+/// Builds the output stage: the same chain of units for every channel,
+/// a plain pass-through when the chain is empty
+pub fn build(chain: &FxChain, channels: usize, sample_rate: f64) -> BigBlockAdapter32 {
+    let mut net = Net32::new(channels, channels);
+
+    for ch in 0..channels {
+        let mut prev = None;
+
+        for (i, unit) in chain.0.iter().enumerate() {
+            let id = net.push(fx_unit(unit, (ch * chain.0.len() + i) as i64));
+
+            match prev {
+                Some(prev_id) => net.connect(prev_id, 0, id, 0),
+                None => net.connect_input(ch, id, 0),
+            }
+
+            prev = Some(id);
+        }
+
+        match prev {
+            Some(id) => net.connect_output(id, 0, ch),
+            None => {
+                let id = net.push(Box::new(pass()));
+                net.connect_input(ch, id, 0);
+                net.connect_output(id, 0, ch);
+            }
+        }
+    }
+
+    net.set_sample_rate(sample_rate);
+    net.check();
+    log::debug!("created effects network: {}", net.display());
+
+    let mut fx_be = BigBlockAdapter32::new(Box::new(net));
+
+    fx_be.allocate();
+
+    fx_be
+}
+
+/// One input, one output
+fn fx_unit(unit: &FxUnit, seed: i64) -> Box<dyn AudioUnit32> {
+    match *unit {
+        FxUnit::Reverb {
+            room_size,
+            time,
+            mix,
+        } => {
+            let mix = mix.clamp(0.0, 1.0);
+            Box::new(
+                (pass() * (1.0 - mix))
+                    & ((split::<U2>()
+                        >> reverb_stereo(room_size.max(1.0) as f64, time.max(0.0) as f64, 0.5)
+                        >> join::<U2>())
+                        * mix),
+            )
+        }
+        FxUnit::Delay {
+            time,
+            feedback: fb,
+            mix,
+        } => {
+            let mix = mix.clamp(0.0, 1.0);
+            Box::new(
+                (pass() * (1.0 - mix))
+                    & (feedback(delay(time.max(0.0) as f64) * fb.clamp(0.0, 0.99)) * mix),
+            )
+        }
+        FxUnit::Eq { freq, q, gain } => {
+            // a bell at zero gain has no response to shape, so cut at most by 60 dB
+            Box::new(bell_hz(freq, q.max(0.01), gain.max(db_amp(-60.0))))
+        }
+        FxUnit::Chorus {
+            separation,
+            variation,
+            mod_frequency,
+        } => Box::new(chorus(seed, separation, variation, mod_frequency)),
+        FxUnit::Limiter { attack, release } => {
+            Box::new(limiter((attack.max(0.0), release.max(0.0))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: usize = 256;
+
+    fn units() -> Vec<FxUnit> {
+        vec![
+            FxUnit::Eq {
+                freq: 800.0,
+                q: 1.0,
+                gain: 1.5,
+            },
+            FxUnit::Chorus {
+                separation: 0.015,
+                variation: 0.005,
+                mod_frequency: 0.3,
+            },
+            FxUnit::Delay {
+                time: 0.01,
+                feedback: 0.5,
+                mix: 0.3,
+            },
+            FxUnit::Reverb {
+                room_size: 10.0,
+                time: 1.5,
+                mix: 0.25,
+            },
+            FxUnit::Limiter {
+                attack: 0.005,
+                release: 0.1,
+            },
+        ]
+    }
+
+    fn process(fx_be: &mut BigBlockAdapter32, channels: usize) -> Vec<Vec<f32>> {
+        let input = (0..channels)
+            .map(|ch| {
+                (0..BLOCK)
+                    .map(|i| (i as f32 * 0.05 * (ch + 1) as f32).sin())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let input_slices = input.iter().map(|ch| ch.as_slice()).collect::<Vec<_>>();
+        let mut output = vec![vec![0_f32; BLOCK]; channels];
+        let mut output_slices = output
+            .iter_mut()
+            .map(|ch| ch.as_mut_slice())
+            .collect::<Vec<_>>();
+
+        fx_be.process(BLOCK, input_slices.as_slice(), output_slices.as_mut_slice());
+
+        output
+    }
+
+    #[test]
+    fn empty_chain_passes_through() {
+        let mut fx_be = build(&FxChain::default(), 2, 44100.0);
+
+        let output = process(&mut fx_be, 2);
+
+        assert_eq!(output[0][10], (10.0_f32 * 0.05).sin());
+        assert_eq!(output[1][10], (10.0_f32 * 0.1).sin());
+    }
+
+    #[test]
+    fn builds_every_unit() {
+        for unit in units() {
+            for channels in [1, 2] {
+                let mut fx_be = build(&FxChain(vec![unit]), channels, 48000.0);
+                for _ in 0..4 {
+                    let output = process(&mut fx_be, channels);
+                    assert_eq!(output.len(), channels);
+                    assert!(output.iter().flatten().all(|s| s.is_finite()), "{unit:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chains_units_in_order() {
+        let mut fx_be = build(&FxChain(units()), 2, 44100.0);
+
+        for _ in 0..8 {
+            let output = process(&mut fx_be, 2);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+        }
+    }
+
+    #[test]
+    fn eq_stays_finite_at_zero_gain() {
+        for gain in [0.0, -1.0] {
+            let unit = FxUnit::Eq {
+                freq: 800.0,
+                q: 1.0,
+                gain,
+            };
+            let mut fx_be = build(&FxChain(vec![unit]), 1, 44100.0);
+            for _ in 0..4 {
+                let output = process(&mut fx_be, 1);
+                assert!(output.iter().flatten().all(|s| s.is_finite()), "{unit:?}");
+            }
+        }
+    }
+}
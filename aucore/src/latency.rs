@@ -0,0 +1,205 @@
+use std::f64::consts::TAU;
+
+use app_core::play::{Latency, Window};
+
+use crate::analyzer::window;
+
+/// length of the test chirp
+const PROBE_SECONDS: f64 = 0.05;
+const PROBE_LEVEL: f32 = 0.5;
+/// the chirp sweeps between these frequencies, within most speakers and microphones
+const PROBE_LOW: f64 = 300.0;
+const PROBE_HIGH: f64 = 6000.0;
+/// longest round trip looked for
+pub const MAX_LATENCY_SECONDS: f64 = 0.5;
+/// normalised correlation below this is taken as the probe not being heard
+const MIN_CORRELATION: f32 = 0.3;
+/// multiply-adds spent looking for the signal per block, spreading the search over blocks
+const SEARCH_BUDGET: usize = 1 << 18;
+
+/// Plays a chirp through the output and finds it in the input
+pub struct LatencyProbe {
+    signal: Vec<f32>,
+    emitted: usize,
+    recorded: Vec<f32>,
+    /// samples to record for the signal to arrive at the longest latency
+    listen: usize,
+    sample_rate: f64,
+    signal_energy: f32,
+    /// next lag to try
+    lag: usize,
+    /// energy of the recorded input under the signal at the last lag tried
+    window_energy: f32,
+    /// lag and normalised correlation of the best match so far
+    best: Option<(usize, f32)>,
+}
+
+impl LatencyProbe {
+    pub fn new(sample_rate: f64) -> Self {
+        let len = (PROBE_SECONDS * sample_rate) as usize;
+        let sweep = (PROBE_HIGH - PROBE_LOW) / PROBE_SECONDS;
+
+        let signal = window(Window::Hann, len)
+            .into_iter()
+            .enumerate()
+            .map(|(i, w)| {
+                let t = i as f64 / sample_rate;
+                let phase = TAU * (PROBE_LOW * t + sweep * t * t / 2.0);
+                PROBE_LEVEL * w * phase.sin() as f32
+            })
+            .collect::<Vec<_>>();
+
+        let listen = signal.len() + (MAX_LATENCY_SECONDS * sample_rate) as usize;
+
+        Self {
+            recorded: Vec::with_capacity(listen),
+            listen,
+            signal_energy: signal.iter().map(|s| s * s).sum(),
+            signal,
+            emitted: 0,
+            sample_rate,
+            lag: 0,
+            window_energy: 0.0,
+            best: None,
+        }
+    }
+
+    /// Writes the next part of the test signal over every output channel, silence after it
+    pub fn emit(&mut self, output: &mut [Vec<f32>]) {
+        let len = output.first().map_or(0, |ch| ch.len());
+
+        for (i, frame) in (self.emitted..self.emitted + len).enumerate() {
+            let sample = self.signal.get(frame).copied().unwrap_or_default();
+            for ch in output.iter_mut() {
+                ch[i] = sample;
+            }
+        }
+
+        self.emitted += len;
+    }
+
+    /// Records input, from the block the signal starts in
+    pub fn push(&mut self, input: &[f32]) {
+        let take = input.len().min(self.listen - self.recorded.len());
+        self.recorded.extend_from_slice(&input[..take]);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.recorded.len() >= self.listen
+    }
+
+    /// Tries the next lags of the signal in the recorded input, as many as fit
+    /// in [`SEARCH_BUDGET`], `true` once every lag up to `MAX_LATENCY_SECONDS` has been tried
+    pub fn search(&mut self) -> bool {
+        if !self.is_complete() {
+            return false;
+        }
+
+        let len = self.signal.len();
+        let last = self.recorded.len().saturating_sub(len);
+        let until = last.min(self.lag + (SEARCH_BUDGET / len.max(1)).max(1) - 1);
+
+        for lag in self.lag..=until {
+            self.window_energy = if lag == 0 {
+                self.recorded[..len.min(self.recorded.len())]
+                    .iter()
+                    .map(|s| s * s)
+                    .sum::<f32>()
+            } else {
+                let out = self.recorded[lag - 1];
+                let into = self.recorded[lag + len - 1];
+                (self.window_energy - out * out + into * into).max(0.0)
+            };
+
+            let norm = (self.signal_energy * self.window_energy).sqrt();
+            if norm <= f32::EPSILON {
+                continue;
+            }
+
+            let dot = self
+                .signal
+                .iter()
+                .zip(self.recorded[lag..lag + len].iter())
+                .map(|(s, r)| s * r)
+                .sum::<f32>();
+
+            let correlation = dot / norm;
+            if self.best.map_or(true, |(_, c)| correlation > c) {
+                self.best = Some((lag, correlation));
+            }
+        }
+
+        self.lag = until + 1;
+        self.lag > last
+    }
+
+    /// Lag of the best match of the signal in the recorded input once searched,
+    /// `None` when it wasn't heard
+    pub fn latency(&self) -> Option<Latency> {
+        self.best
+            .filter(|(_, c)| *c >= MIN_CORRELATION)
+            .map(|(samples, _)| Latency {
+                samples,
+                millis: samples as f64 / self.sample_rate * 1000.0,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+    const BLOCK: usize = 256;
+
+    /// runs the probe through a room delaying the output by `delay` samples
+    fn round_trip(delay: usize, gain: f32) -> Option<Latency> {
+        let mut probe = LatencyProbe::new(SAMPLE_RATE);
+        let mut played = vec![];
+        let mut noise = 0x1234_5678_u32;
+
+        while !probe.is_complete() {
+            let from = played.len();
+            let input = (from..from + BLOCK)
+                .map(|n| {
+                    noise ^= noise << 13;
+                    noise ^= noise >> 17;
+                    noise ^= noise << 5;
+                    let hiss = (noise as f32 / u32::MAX as f32 - 0.5) * 0.02;
+                    let heard = n
+                        .checked_sub(delay)
+                        .and_then(|n| played.get(n))
+                        .copied()
+                        .unwrap_or_default();
+                    heard * gain + hiss
+                })
+                .collect::<Vec<f32>>();
+
+            probe.push(input.as_slice());
+
+            let mut output = vec![vec![0.0; BLOCK]; 2];
+            probe.emit(output.as_mut_slice());
+            played.extend_from_slice(output[0].as_slice());
+        }
+
+        // spread over blocks
+        assert!(!probe.search());
+        while !probe.search() {}
+
+        probe.latency()
+    }
+
+    #[test]
+    fn finds_the_round_trip() {
+        for delay in [BLOCK, 1500, 9000] {
+            let latency = round_trip(delay, 0.3).expect("probe heard");
+            assert_eq!(latency.samples, delay);
+            assert!((latency.millis - delay as f64 / 48.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn misses_an_unheard_probe() {
+        assert_eq!(round_trip(1500, 0.0), None);
+    }
+}
@@ -3,10 +3,22 @@ pub use crux_core::{Core, Request};
 pub use app::*;
 
 
+pub mod analyzer;
 pub mod app;
+pub mod calibration;
 mod resolve;
 mod capture;
+pub mod detector;
+pub mod effects;
+pub mod latency;
+pub mod meter;
+#[cfg(feature = "osc")]
+pub mod osc;
+pub mod recorder;
+pub mod render;
+pub mod sequencer;
 pub mod system;
+pub mod voice;
 
 
 cfg_if::cfg_if! {if #[cfg(feature="browser")] {
@@ -0,0 +1,76 @@
+use app_core::play::InputLevel;
+
+/// seconds between level reports
+pub const METER_INTERVAL: f64 = 1.0 / 30.0;
+/// samples at or over this are counted as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Peak, RMS and clips of the raw input, reported once per interval
+#[derive(Default)]
+pub struct LevelMeter {
+    peak: f32,
+    power: f64,
+    clips: usize,
+    frames: usize,
+}
+
+impl LevelMeter {
+    /// Takes in a block, returning the levels since the last report once an interval has passed
+    pub fn push(&mut self, samples: &[f32], sample_rate: f64) -> Option<InputLevel> {
+        for sample in samples {
+            let level = sample.abs();
+            self.peak = self.peak.max(level);
+            self.power += (*sample as f64).powi(2);
+            if level >= CLIP_LEVEL {
+                self.clips += 1;
+            }
+        }
+        self.frames += samples.len();
+
+        (self.frames as f64 >= METER_INTERVAL * sample_rate).then(|| {
+            let level = InputLevel {
+                peak: self.peak,
+                rms: (self.power / self.frames as f64).sqrt() as f32,
+                clips: self.clips,
+            };
+            *self = Self::default();
+            level
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    #[test]
+    fn reports_once_per_interval() {
+        let mut meter = LevelMeter::default();
+        let block = vec![0.5_f32; 256];
+        let per_interval = (METER_INTERVAL * SAMPLE_RATE / 256.0).ceil() as usize;
+
+        let reports = (0..per_interval * 3)
+            .filter_map(|_| meter.push(block.as_slice(), SAMPLE_RATE))
+            .collect::<Vec<_>>();
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].peak, 0.5);
+        assert!((reports[0].rms - 0.5).abs() < 1e-6);
+        assert_eq!(reports[0].clips, 0);
+    }
+
+    #[test]
+    fn counts_clipped_samples() {
+        let mut meter = LevelMeter::default();
+        let block = (0..2048)
+            .map(|i| if i % 512 == 0 { -1.0 } else { 0.1 })
+            .collect::<Vec<f32>>();
+
+        let level = meter.push(block.as_slice(), SAMPLE_RATE).expect("a report");
+
+        assert_eq!(level.clips, 4);
+        assert_eq!(level.peak, 1.0);
+    }
+}
@@ -0,0 +1,298 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use app_core::play::PlayOperation;
+use rosc::{decoder, encoder, OscBundle, OscMessage, OscPacket, OscType};
+
+pub const DEFAULT_PORT: u16 = 9000;
+/// seconds between monitoring broadcasts
+pub const MONITOR_INTERVAL: f64 = 1.0 / 30.0;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// peers silent for longer stop getting broadcasts
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// peers by address, with when they were last heard from
+type Peers = Arc<Mutex<Vec<(SocketAddr, Instant)>>>;
+
+/// Node levels and output samples to send
+struct Monitor {
+    levels: Vec<(usize, f32)>,
+    output: Vec<f32>,
+}
+
+/// OSC over UDP on localhost, controlling node parameters
+/// and sending node levels and output snoop data back to every peer it has heard from
+/// lately, encoding and sending on a thread of its own
+pub struct OscEndpoint {
+    local_addr: SocketAddr,
+    ops: Receiver<PlayOperation>,
+    monitor: SyncSender<Monitor>,
+    listening: Arc<AtomicBool>,
+}
+
+impl OscEndpoint {
+    /// Starts listening on `port`, or any free port when it's 0
+    pub fn bind(port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, port))?;
+        let local_addr = socket.local_addr()?;
+        let listener = socket.try_clone()?;
+        listener.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let (sender, ops) = channel();
+        // one pending broadcast at most, later ones are dropped while it's sent
+        let (monitor, monitors) = sync_channel::<Monitor>(1);
+        let peers = Peers::default();
+        let listening = Arc::new(AtomicBool::new(true));
+
+        {
+            let peers = peers.clone();
+            let listening = listening.clone();
+
+            thread::spawn(move || {
+                let mut buf = [0_u8; decoder::MTU];
+
+                while listening.load(Ordering::Relaxed) {
+                    let (len, addr) = match listener.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(e) if is_timeout(&e) => continue,
+                        Err(e) => {
+                            log::error!("osc receive: {e:?}");
+                            break;
+                        }
+                    };
+
+                    {
+                        let mut peers = peers.lock().unwrap_or_else(PoisonError::into_inner);
+                        match peers.iter_mut().find(|(peer, _)| *peer == addr) {
+                            Some((_, heard)) => *heard = Instant::now(),
+                            None => {
+                                log::info!("osc peer {addr}");
+                                peers.push((addr, Instant::now()));
+                            }
+                        }
+                    }
+
+                    match decoder::decode_udp(&buf[..len]) {
+                        Ok((_, packet)) => {
+                            for op in operations(&packet) {
+                                if sender.send(op).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        Err(e) => log::warn!("osc decode: {e:?}"),
+                    }
+                }
+
+                log::debug!("osc listener exited");
+            });
+        }
+
+        thread::spawn(move || {
+            for Monitor { levels, output } in monitors {
+                let peers = {
+                    let mut peers = peers.lock().unwrap_or_else(PoisonError::into_inner);
+                    expire(&mut peers, Instant::now());
+                    peers.iter().map(|(peer, _)| *peer).collect::<Vec<_>>()
+                };
+
+                if !peers.is_empty() {
+                    send(
+                        &socket,
+                        peers.as_slice(),
+                        levels.as_slice(),
+                        output.as_slice(),
+                    );
+                }
+            }
+
+            log::debug!("osc sender exited");
+        });
+
+        Ok(Self {
+            local_addr,
+            ops,
+            monitor,
+            listening,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The next operation received, if any, without waiting
+    pub fn next_op(&self) -> Option<PlayOperation> {
+        self.ops.try_recv().ok()
+    }
+
+    /// Queues `/node/{f_n}/level` for each node and `/output/snoop` with the output samples
+    /// to send to the peers, dropping them while an earlier broadcast is still being sent
+    pub fn broadcast(&self, levels: Vec<(usize, f32)>, output: &[f32]) {
+        let monitor = Monitor {
+            levels,
+            output: output.to_vec(),
+        };
+
+        if let Err(TrySendError::Disconnected(_)) = self.monitor.try_send(monitor) {
+            log::warn!("osc sender is gone");
+        }
+    }
+}
+
+impl Drop for OscEndpoint {
+    fn drop(&mut self) {
+        self.listening.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Forgets peers not heard from within [`PEER_TIMEOUT`]
+fn expire(peers: &mut Vec<(SocketAddr, Instant)>, now: Instant) {
+    peers.retain(|(peer, heard)| {
+        let live = now.duration_since(*heard) < PEER_TIMEOUT;
+        if !live {
+            log::info!("osc peer {peer} went silent");
+        }
+        live
+    });
+}
+
+fn send(socket: &UdpSocket, peers: &[SocketAddr], levels: &[(usize, f32)], output: &[f32]) {
+    let mut content = levels
+        .iter()
+        .map(|(f_n, level)| {
+            OscPacket::Message(OscMessage {
+                addr: format!("/node/{f_n}/level"),
+                args: vec![OscType::Float(*level)],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    content.push(OscPacket::Message(OscMessage {
+        addr: "/output/snoop".to_string(),
+        args: output.iter().map(|s| OscType::Float(*s)).collect(),
+    }));
+
+    let packet = OscPacket::Bundle(OscBundle {
+        timetag: (0, 1).into(),
+        content,
+    });
+
+    match encoder::encode(&packet) {
+        Ok(bytes) => {
+            for peer in peers.iter() {
+                if let Err(e) = socket.send_to(bytes.as_slice(), peer) {
+                    log::warn!("osc send to {peer}: {e:?}");
+                }
+            }
+        }
+        Err(e) => log::error!("osc encode: {e:?}"),
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// The operations addressed by a packet, messages in bundles included
+pub fn operations(packet: &OscPacket) -> Vec<PlayOperation> {
+    match packet {
+        OscPacket::Message(msg) => operation(msg).into_iter().collect(),
+        OscPacket::Bundle(bundle) => bundle.content.iter().flat_map(operations).collect(),
+    }
+}
+
+fn operation(msg: &OscMessage) -> Option<PlayOperation> {
+    let value = match msg.args.first()? {
+        OscType::Float(v) => *v,
+        OscType::Double(v) => *v as f32,
+        OscType::Int(v) => *v as f32,
+        arg => {
+            log::warn!("osc {} unsupported argument {arg:?}", msg.addr);
+            return None;
+        }
+    };
+
+    let path = msg
+        .addr
+        .trim_start_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+
+    match path.as_slice() {
+        ["master", "gain"] => Some(PlayOperation::MasterGain(value)),
+        ["node", f_n, param] => {
+            let f_n = f_n.parse::<usize>().ok()?;
+            match *param {
+                "freq" => Some(PlayOperation::NodeFreq(f_n, value)),
+                "q" => Some(PlayOperation::NodeQ(f_n, value)),
+                "sensitivity" => Some(PlayOperation::NodeSensitivity(f_n, value)),
+                "gain" => Some(PlayOperation::NodeGain(f_n, value)),
+                "pan" => Some(PlayOperation::NodePan(f_n, value)),
+                _ => None,
+            }
+        }
+        _ => {
+            log::debug!("osc {} not addressed", msg.addr);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscPacket {
+        OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args,
+        })
+    }
+
+    #[test]
+    fn maps_addresses_to_operations() {
+        let packet = OscPacket::Bundle(OscBundle {
+            timetag: (0, 1).into(),
+            content: vec![
+                message("/node/3/freq", vec![OscType::Float(440.0)]),
+                message("/node/3/q", vec![OscType::Double(0.5)]),
+                message("/master/gain", vec![OscType::Int(2)]),
+                message("/node/x/freq", vec![OscType::Float(440.0)]),
+                message("/node/3/freq", vec![OscType::String("440".into())]),
+                message("/unknown", vec![OscType::Float(1.0)]),
+            ],
+        });
+
+        assert_eq!(
+            operations(&packet),
+            vec![
+                PlayOperation::NodeFreq(3, 440.0),
+                PlayOperation::NodeQ(3, 0.5),
+                PlayOperation::MasterGain(2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn expires_silent_peers() {
+        let now = Instant::now();
+        let addr = |port| SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let mut peers = vec![
+            (addr(9001), now - PEER_TIMEOUT - Duration::from_secs(1)),
+            (addr(9002), now - Duration::from_secs(1)),
+        ];
+
+        expire(&mut peers, now);
+
+        assert_eq!(peers, vec![(addr(9002), now - Duration::from_secs(1))]);
+    }
+}
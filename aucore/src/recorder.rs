@@ -0,0 +1,143 @@
+use std::io::Cursor;
+use std::thread;
+
+use anyhow::Result;
+use futures::channel::oneshot;
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+/// Multichannel buffer of processed output, allocated up front for its maximum length
+#[derive(Default)]
+pub struct Recorder {
+    data: Vec<Vec<f32>>,
+    max_frames: usize,
+}
+
+impl Recorder {
+    pub fn new(max_s: f64, sample_rate: f64, channels: usize) -> Self {
+        let max_frames = (max_s.max(0.0) * sample_rate) as usize;
+
+        Self {
+            data: Self::buffers(channels, max_frames),
+            max_frames,
+        }
+    }
+
+    fn buffers(channels: usize, max_frames: usize) -> Vec<Vec<f32>> {
+        (0..channels)
+            .map(|_| Vec::with_capacity(max_frames))
+            .collect()
+    }
+
+    /// Appends a block, dropping whatever exceeds the maximum length
+    pub fn push(&mut self, block: &[Vec<f32>]) {
+        if self.data.len() != block.len() {
+            if self.frames() > 0 {
+                log::warn!("recording channels changed, restarting");
+            }
+            self.data = Self::buffers(block.len(), self.max_frames);
+        }
+
+        let room = self.max_frames - self.frames();
+        for (ch, data) in self.data.iter_mut().zip(block) {
+            ch.extend_from_slice(&data[..data.len().min(room)]);
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.data.first().map_or(0, |ch| ch.len())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frames() >= self.max_frames
+    }
+
+    pub fn finish(self, sample_rate: f64) -> Result<Vec<u8>> {
+        encode_wav(self.data.as_slice(), sample_rate)
+    }
+
+    /// Encodes the recording on its own thread so that a long one doesn't hold up processing,
+    /// the receiver is cancelled when encoding fails
+    pub fn finish_detached(self, sample_rate: f64) -> Result<oneshot::Receiver<Vec<u8>>> {
+        let (sender, receiver) = oneshot::channel();
+
+        thread::Builder::new()
+            .name("aucore recording".to_string())
+            .spawn(move || match self.finish(sample_rate) {
+                Ok(wav) => {
+                    _ = sender.send(wav);
+                }
+                Err(e) => log::error!("encode recording: {e:?}"),
+            })?;
+
+        Ok(receiver)
+    }
+}
+
+/// Encodes non-interleaved channels as float WAV bytes
+pub fn encode_wav(channels: &[Vec<f32>], sample_rate: f64) -> Result<Vec<u8>> {
+    let spec = WavSpec {
+        channels: channels.len().max(1) as u16,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+
+    let frames = channels.first().map_or(0, |ch| ch.len());
+    let mut bytes = Cursor::new(Vec::new());
+
+    {
+        let mut writer = WavWriter::new(&mut bytes, spec)?;
+
+        for i in 0..frames {
+            for ch in channels {
+                writer.write_sample(ch.get(i).copied().unwrap_or_default())?;
+            }
+        }
+
+        writer.finalize()?;
+    }
+
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavReader;
+
+    #[test]
+    fn stops_growing_at_max_length() {
+        let mut recorder = Recorder::new(1.0, 100.0, 2);
+        let block = vec![vec![0.5; 64], vec![-0.5; 64]];
+        let capacity = recorder.data[0].capacity();
+
+        recorder.push(&block);
+        assert_eq!(recorder.frames(), 64);
+        assert!(!recorder.is_full());
+
+        recorder.push(&block);
+        recorder.push(&block);
+        assert_eq!(recorder.frames(), 100);
+        assert!(recorder.is_full());
+        assert_eq!(recorder.data[0].capacity(), capacity);
+    }
+
+    #[test]
+    fn encodes_recorded_channels() {
+        let mut recorder = Recorder::new(1.0, 48000.0, 2);
+        recorder.push(&[vec![0.25; 128], vec![-0.25; 128]]);
+
+        let encoded = recorder.finish_detached(48000.0).unwrap();
+        let bytes = futures::executor::block_on(encoded).unwrap();
+
+        let mut reader = WavReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48000);
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(samples.len(), 256);
+        assert_eq!(&samples[..2], &[0.25, -0.25]);
+    }
+}
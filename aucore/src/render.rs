@@ -0,0 +1,190 @@
+use std::io::Read;
+
+use anyhow::Result;
+use app_core::{instrument::Node, play::FxChain, tuner::TuningValue};
+use hound::{SampleFormat, WavReader};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    recorder::encode_wav,
+    system::{System, DEFAULT_CHANNELS},
+};
+
+const RENDER_BLOCK: usize = 512;
+const RENDER_TAIL_S: f64 = 1.0;
+
+/// The nodes and tuning `PlayOperation::Config` would carry, for rendering offline
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RenderSetup {
+    pub nodes: Vec<Node>,
+    pub tuning: Vec<TuningValue>,
+    #[serde(default)]
+    pub effects: FxChain,
+    /// output channels, stereo when not set
+    #[serde(default)]
+    pub channels: Option<usize>,
+}
+
+/// Runs the first channel of a WAV recording through a `System`
+/// at the recording's sample rate and returns the output as multichannel float WAV bytes
+pub fn render_wav<R: Read>(input: R, setup: &RenderSetup) -> Result<Vec<u8>> {
+    let reader = WavReader::new(input)?;
+    let spec = reader.spec();
+    let in_channels = spec.channels as usize;
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let input = samples
+        .into_iter()
+        .step_by(in_channels.max(1))
+        .collect::<Vec<_>>();
+
+    render(input.as_slice(), spec.sample_rate as f64, setup)
+}
+
+/// Runs mono input through a `System` and returns the output as float WAV bytes,
+/// failing when the setup's nodes and tuning don't pair up
+pub fn render(input: &[f32], sample_rate: f64, setup: &RenderSetup) -> Result<Vec<u8>> {
+    let mut sys = System::new(
+        setup.nodes.as_slice(),
+        setup.tuning.as_slice(),
+        setup.channels.unwrap_or(DEFAULT_CHANNELS),
+        sample_rate,
+        &setup.effects,
+    )?;
+
+    let tail = vec![0_f32; (RENDER_TAIL_S * sys.sample_rate) as usize];
+    let mut output = vec![vec![0_f32; RENDER_BLOCK]; sys.channels];
+    let mut rendered = vec![vec![]; sys.channels];
+
+    for block in input.chunks(RENDER_BLOCK).chain(tail.chunks(RENDER_BLOCK)) {
+        let size = block.len();
+        let mut output_slices = output
+            .iter_mut()
+            .map(|ch| &mut ch[..size])
+            .collect::<Vec<_>>();
+
+        sys.process(size, &[block], output_slices.as_mut_slice());
+
+        for (ch, data) in rendered.iter_mut().zip(output.iter()) {
+            ch.extend_from_slice(&data[..size]);
+        }
+    }
+
+    encode_wav(rendered.as_slice(), sys.sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+    use std::io::Cursor;
+
+    fn setup() -> RenderSetup {
+        let nodes = (1..=4)
+            .rev()
+            .map(|f_n| {
+                let freq = 110.0 * (f_n * 2) as f32 - 110.0;
+                Node {
+                    freq: (freq, freq + 110.0),
+                    f_n,
+                    pan: if f_n > 2 { 1.0 } else { -1.0 },
+                    triggered: 0.0,
+                    voice: Default::default(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let tuning = nodes.iter().map(|n| (n.f_n, n.freq.0, 0.5)).collect();
+
+        RenderSetup {
+            nodes,
+            tuning,
+            effects: FxChain::default(),
+            channels: None,
+        }
+    }
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    #[test]
+    fn renders_wav_input() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let len = 4410;
+        let mut input = Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut input, spec).unwrap();
+            for i in 0..len {
+                let s = (i as f32 * 440.0 * std::f32::consts::TAU / SAMPLE_RATE as f32).sin();
+                writer.write_sample((s * i16::MAX as f32) as i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let rendered = render_wav(input.into_inner().as_slice(), &setup()).unwrap();
+
+        let reader = WavReader::new(rendered.as_slice()).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(
+            reader.len() as usize,
+            (len + (RENDER_TAIL_S * SAMPLE_RATE as f64) as usize) * 2
+        );
+    }
+
+    #[test]
+    fn renders_at_input_sample_rate() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut input = Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut input, spec).unwrap();
+            writer.write_sample(0_f32).unwrap();
+            writer.finalize().unwrap();
+        }
+
+        let rendered = render_wav(input.into_inner().as_slice(), &setup()).unwrap();
+
+        let reader = WavReader::new(rendered.as_slice()).unwrap();
+        assert_eq!(reader.spec().sample_rate, 48000);
+    }
+
+    #[test]
+    fn renders_requested_channels() {
+        let mut setup = setup();
+        setup.channels = Some(4);
+
+        let rendered = render(&[0.5; 256], SAMPLE_RATE as f64, &setup).unwrap();
+
+        let reader = WavReader::new(rendered.as_slice()).unwrap();
+        assert_eq!(reader.spec().channels, 4);
+    }
+
+    #[test]
+    fn fails_for_a_setup_that_does_not_pair_up() {
+        let mut setup = setup();
+        setup.tuning.reverse();
+        assert!(render(&[0.5; 256], SAMPLE_RATE as f64, &setup).is_err());
+
+        setup.tuning.truncate(1);
+        assert!(render(&[0.5; 256], SAMPLE_RATE as f64, &setup).is_err());
+
+        setup.nodes.clear();
+        assert!(render(&[0.5; 256], SAMPLE_RATE as f64, &setup).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use app_core::play::PlayOperationOutput;
+use app_core::play::{PlayError, PlayOperationOutput};
 use crux_core::capability::CapabilityContext;
 use crux_macros::Capability;
 
@@ -15,17 +15,23 @@ where
         Self { context }
     }
 
-    pub fn resolve_success(&self, success: bool) {
+    pub fn resolve_success(&self) {
+        self.resolve(PlayOperationOutput::Success)
+    }
+
+    pub fn resolve_capturing(&self, sample_rate: f64) {
+        self.resolve(PlayOperationOutput::Capturing(sample_rate))
+    }
+
+    pub fn resolve_failure(&self, error: PlayError) {
+        self.resolve(PlayOperationOutput::Failure(error))
+    }
+
+    fn resolve(&self, output: PlayOperationOutput) {
         let ctx = self.context.clone();
 
         self.context.spawn(async move {
-            _ = ctx
-                .notify_shell(if success {
-                    PlayOperationOutput::Success
-                } else {
-                    PlayOperationOutput::Failure
-                })
-                .await;
+            _ = ctx.notify_shell(output).await;
         })
     }
 }
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+
+use app_core::{instrument::Node, midi::MidiMessage};
+
+/// Plays back timed MIDI messages on the audio block clock
+#[derive(Default, Debug)]
+pub struct Sequencer {
+    events: Vec<(f64, MidiMessage)>,
+    sample_rate: f64,
+    /// frames since the start of the file
+    position: u64,
+    cursor: usize,
+    playing: bool,
+    notes: NoteGates,
+}
+
+impl Sequencer {
+    /// `events` are timed in seconds, in order
+    pub fn new(events: Vec<(f64, MidiMessage)>, sample_rate: f64) -> Self {
+        Self {
+            events,
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Starts from the current position, or over once finished
+    pub fn play(&mut self) {
+        if self.is_finished() {
+            self.seek(0.0);
+        }
+        self.playing = !self.is_finished();
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.notes.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.events.len()
+    }
+
+    /// position in seconds
+    pub fn position(&self) -> f64 {
+        self.position as f64 / self.sample_rate
+    }
+
+    /// Moves to `time` seconds, skipping any messages before it
+    pub fn seek(&mut self, time: f64) {
+        self.position = (time.max(0.0) * self.sample_rate).round() as u64;
+        self.cursor = self
+            .events
+            .partition_point(|(t, _)| self.frame(*t) < self.position);
+        self.notes.clear();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        let time = self.position();
+        self.sample_rate = sample_rate;
+        self.seek(time);
+    }
+
+    /// Moves on by one block of `size` frames,
+    /// calling `f` with each message due in it and its frame offset into the block
+    pub fn advance<F>(&mut self, size: usize, mut f: F)
+    where
+        F: FnMut(usize, MidiMessage),
+    {
+        if !self.playing {
+            return;
+        }
+
+        let end = self.position + size as u64;
+
+        while let Some((time, message)) = self.events.get(self.cursor) {
+            let frame = self.frame(*time);
+            if frame >= end {
+                break;
+            }
+
+            f(frame.saturating_sub(self.position) as usize, *message);
+            self.cursor += 1;
+        }
+
+        self.position = end;
+
+        if self.is_finished() {
+            log::info!("midi playback finished at {:.2}s", self.position());
+            self.playing = false;
+        }
+    }
+
+    /// Moves on by one block like [`Sequencer::advance`], calling `f` with each gate change
+    /// of the nodes nearest the notes due in it: the node, the frame offset and the level
+    pub fn advance_gates<F>(&mut self, size: usize, nodes: &[Node], mut f: F)
+    where
+        F: FnMut(usize, usize, f32),
+    {
+        let mut notes = std::mem::take(&mut self.notes);
+
+        self.advance(size, |offset, message| {
+            if let Some((f_n, level)) = notes.gate(nodes, message) {
+                f(f_n, offset, level);
+            }
+        });
+
+        // notes left sounding end with the file, the caller releases their gates
+        if !self.is_finished() {
+            self.notes = notes;
+        }
+    }
+
+    fn frame(&self, time: f64) -> u64 {
+        (time * self.sample_rate).round() as u64
+    }
+}
+
+/// Sounding notes by the node they play on, so that overlapping notes
+/// on one node keep its gate open until the last of them ends
+#[derive(Default, Debug)]
+struct NoteGates {
+    /// node of each sounding channel and note
+    notes: BTreeMap<(u8, u8), usize>,
+    /// count of sounding notes by node
+    held: BTreeMap<usize, usize>,
+}
+
+impl NoteGates {
+    /// Node and gate level `message` changes, if any
+    fn gate(&mut self, nodes: &[Node], message: MidiMessage) -> Option<(usize, f32)> {
+        match message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => {
+                let f_n = node_for(nodes, note)?;
+                if self.notes.insert((channel, note), f_n).is_none() {
+                    *self.held.entry(f_n).or_default() += 1;
+                }
+
+                Some((f_n, velocity as f32 / 127.0))
+            }
+            MidiMessage::NoteOn { channel, note, .. } | MidiMessage::NoteOff { channel, note } => {
+                let f_n = self.notes.remove(&(channel, note))?;
+                let held = self.held.get_mut(&f_n)?;
+                *held -= 1;
+
+                if *held == 0 {
+                    _ = self.held.remove(&f_n);
+                    Some((f_n, 0.0))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.notes.clear();
+        self.held.clear();
+    }
+}
+
+/// The node nearest in pitch to `note`
+pub fn node_for(nodes: &[Node], note: u8) -> Option<usize> {
+    let freq = 440.0 * 2_f32.powf((note as f32 - 69.0) / 12.0);
+
+    nodes
+        .iter()
+        .min_by(|a, b| {
+            let a = (a.freq.0 / freq).log2().abs();
+            let b = (b.freq.0 / freq).log2().abs();
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|n| n.f_n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            channel: 0,
+            note,
+            velocity: 100,
+        }
+    }
+
+    fn note_off(note: u8) -> MidiMessage {
+        MidiMessage::NoteOff { channel: 0, note }
+    }
+
+    fn collect(seq: &mut Sequencer, size: usize) -> Vec<(usize, MidiMessage)> {
+        let mut due = vec![];
+        seq.advance(size, |offset, message| due.push((offset, message)));
+        due
+    }
+
+    #[test]
+    fn plays_on_the_block_clock() {
+        let mut seq = Sequencer::new(
+            vec![
+                (0.0, note_on(60)),
+                (0.01, note_off(60)),
+                (0.03, note_on(64)),
+            ],
+            1000.0,
+        );
+
+        assert!(collect(&mut seq, 16).is_empty(), "stopped");
+
+        seq.play();
+        assert_eq!(
+            collect(&mut seq, 16),
+            vec![(0, note_on(60)), (10, note_off(60))]
+        );
+        assert!(collect(&mut seq, 8).is_empty());
+        assert_eq!(collect(&mut seq, 8), vec![(6, note_on(64))]);
+        assert!(seq.is_finished());
+        assert!(!seq.is_playing());
+    }
+
+    #[test]
+    fn seeks_and_stops() {
+        let mut seq = Sequencer::new(
+            vec![(0.0, note_on(60)), (0.5, note_off(60)), (1.0, note_on(64))],
+            1000.0,
+        );
+
+        seq.seek(0.75);
+        seq.play();
+        assert_eq!(collect(&mut seq, 256), vec![(250, note_on(64))]);
+
+        seq.seek(0.0);
+        seq.play();
+        assert_eq!(collect(&mut seq, 4), vec![(0, note_on(60))]);
+        seq.stop();
+        assert!(collect(&mut seq, 1024).is_empty());
+
+        seq.set_sample_rate(2000.0);
+        assert_eq!(seq.position(), 0.004);
+        seq.play();
+        assert_eq!(collect(&mut seq, 1000), vec![(992, note_off(60))]);
+    }
+
+    fn nodes() -> Vec<Node> {
+        [220.0, 330.0, 440.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, freq)| Node {
+                freq: (freq, freq * 2.0),
+                f_n: i + 1,
+                pan: 0.0,
+                triggered: 0.0,
+                voice: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn maps_notes_to_nearest_node() {
+        let nodes = nodes();
+
+        assert_eq!(node_for(&nodes, 69), Some(3));
+        assert_eq!(node_for(&nodes, 57), Some(1));
+        assert_eq!(node_for(&nodes, 64), Some(2));
+        assert_eq!(node_for(&[], 64), None);
+    }
+
+    #[test]
+    fn keeps_a_node_open_while_any_of_its_notes_sound() {
+        let nodes = nodes();
+        // 69 and 70 both play on the 440 node
+        let mut seq = Sequencer::new(
+            vec![
+                (0.0, note_on(69)),
+                (0.002, note_on(70)),
+                (0.004, note_off(69)),
+                (0.006, note_off(70)),
+                (0.008, note_on(57)),
+            ],
+            1000.0,
+        );
+        seq.play();
+
+        let mut gates = vec![];
+        seq.advance_gates(16, &nodes, |f_n, offset, level| {
+            gates.push((f_n, offset, level))
+        });
+
+        assert_eq!(
+            gates,
+            vec![
+                (3, 0, 100.0 / 127.0),
+                (3, 2, 100.0 / 127.0),
+                (3, 6, 0.0),
+                (1, 8, 100.0 / 127.0)
+            ]
+        );
+        assert!(seq.is_finished());
+        assert!(!seq.is_playing());
+
+        // starts over, nothing left held from the first run
+        seq.play();
+        gates.clear();
+        seq.advance_gates(4, &nodes, |f_n, offset, level| {
+            gates.push((f_n, offset, level))
+        });
+        assert_eq!(gates, vec![(3, 0, 100.0 / 127.0), (3, 2, 100.0 / 127.0)]);
+    }
+}
@@ -1,5 +1,7 @@
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, Thread};
+use std::time::Duration;
 
 use anyhow::Result;
 use futures::channel::mpsc::{unbounded, UnboundedSender};
@@ -7,58 +9,68 @@ use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
 use futures::StreamExt;
 
-use lazy_static::lazy_static;
-use app_core::play::{PlayOperation, PlayOperationOutput};
+use app_core::play::{AudioDevice, DeviceSelection, PlayError, PlayOperation, PlayOperationOutput};
 
 pub use futures::channel::mpsc::UnboundedReceiver;
 
-use crate::{Effect, RedSirenAUCapabilities, ViewModel};
+use crate::{Effect, RedSirenAUCapabilities};
+
+mod capture_stream;
+mod ports;
+
+pub use capture_stream::CaptureReceiver;
+
+use capture_stream::{capture_channel, CaptureSender};
+use ports::{CorePorts, BLOCK};
 
 #[cfg_attr(not(any(feature = "android", feature = "ios")), allow(dead_code))]
 pub type Core = crate::Core<crate::Effect, crate::RedSirenAU>;
 
-lazy_static! {
-    static ref CORE: Arc<Mutex<Core>> = Arc::new(Mutex::new(Core::new::<RedSirenAUCapabilities>()));
-}
+/// longest the processing thread sleeps without being woken, only to notice the bridge going away
+const IDLE_WAIT: Duration = Duration::from_millis(100);
 
 pub trait StreamerUnit {
-    fn init(&self) -> Result<()>;
+    /// opens the streams and returns the sample rate and output channels negotiated with the device
+    fn init(&self) -> Result<(f64, usize)>;
     fn pause(&self) -> Result<()>;
     fn start(&self) -> Result<()>;
+    fn input_devices(&self) -> Result<Vec<AudioDevice>>;
+    fn output_devices(&self) -> Result<Vec<AudioDevice>>;
+}
+
+/// Messages to the processing thread, kept apart from the audio rings
+enum Control {
+    /// an operation for the core, resolved through the sender if there is one
+    Op(PlayOperation, Option<UnboundedSender<PlayOperationOutput>>),
+    /// the core end of newly opened streams
+    Attach(CorePorts),
+    /// where captures go from now on
+    Capture(CaptureSender),
 }
 
 #[derive(Clone)]
 #[cfg_attr(not(any(feature = "android", feature = "ios")), allow(dead_code))]
 struct CoreStreamer {
-    pub op_receiver: Arc<Mutex<Receiver<PlayOperation>>>,
-    pub op_sender: Arc<Mutex<Sender<PlayOperation>>>,
-    pub resolve_sender: Arc<Mutex<UnboundedSender<PlayOperationOutput>>>,
-    pub render_sender: Arc<Mutex<Sender<ViewModel>>>,
-    pub render_receiver: Arc<Mutex<Receiver<ViewModel>>>,
-    pub input_sender: Arc<Mutex<Sender<Vec<Vec<f32>>>>>,
-    pub input_receiver: Arc<Mutex<Receiver<Vec<Vec<f32>>>>>,
+    control: Sender<Control>,
+    /// woken when there's an operation or a block of input to process
+    processor: Thread,
+    /// the latest request, failed when a device errors
+    resolve_sender: Arc<Mutex<UnboundedSender<PlayOperationOutput>>>,
+    /// devices the next streams are opened on
+    selection: Arc<Mutex<DeviceSelection>>,
 }
 
 #[cfg_attr(not(any(feature = "android", feature = "ios")), allow(dead_code))]
 impl CoreStreamer {
-    fn new() -> (Self, UnboundedReceiver<PlayOperationOutput>) {
-        let (render_sender, render_receiver) = channel::<ViewModel>();
-        let (op_sender, op_receiver) = channel::<PlayOperation>();
-        let (input_sender, input_receiver) = channel::<Vec<Vec<f32>>>();
-        let (resolve_sender, resolve_receiver) = unbounded::<PlayOperationOutput>();
-
-        (
-            Self {
-                render_sender: Arc::new(Mutex::new(render_sender)),
-                render_receiver: Arc::new(Mutex::new(render_receiver)),
-                resolve_sender: Arc::new(Mutex::new(resolve_sender)),
-                op_sender: Arc::new(Mutex::new(op_sender)),
-                op_receiver: Arc::new(Mutex::new(op_receiver)),
-                input_sender: Arc::new(Mutex::new(input_sender)),
-                input_receiver: Arc::new(Mutex::new(input_receiver)),
-            },
-            resolve_receiver,
-        )
+    fn new(control: Sender<Control>, processor: Thread) -> Self {
+        let (resolve_sender, _) = unbounded::<PlayOperationOutput>();
+
+        Self {
+            control,
+            processor,
+            resolve_sender: Arc::new(Mutex::new(resolve_sender)),
+            selection: Default::default(),
+        }
     }
 
     fn forward(
@@ -66,12 +78,165 @@ impl CoreStreamer {
         event: PlayOperation,
         resolve_id_sender: UnboundedSender<PlayOperationOutput>,
     ) {
-        let op_sender = self.op_sender.lock().expect("lock op sender");
+        let mut resolve = self
+            .resolve_sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *resolve = resolve_id_sender.clone();
+
+        self.send(Control::Op(event, Some(resolve_id_sender)));
+    }
+
+    /// Hands the core end of new streams to the processing thread
+    fn attach(&self, ports: CorePorts) {
+        self.send(Control::Attach(ports));
+    }
 
-        let mut resolve = self.resolve_sender.lock().expect("lock resolve");
-        *resolve = resolve_id_sender;
+    /// Fails the latest request, for device error callbacks
+    fn fail(&self, error: PlayError) {
+        let resolve = self
+            .resolve_sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        respond(&resolve, PlayOperationOutput::Failure(error));
+    }
+
+    /// Remembers the devices to open streams on, as long as they are listed
+    fn select(&self, selection: DeviceSelection) -> Result<()> {
+        if let Some(id) = &selection.input {
+            listed(id, self.input_devices()?.as_slice())?;
+        }
+        if let Some(id) = &selection.output {
+            listed(id, self.output_devices()?.as_slice())?;
+        }
+
+        *self.selection.lock().unwrap_or_else(PoisonError::into_inner) = selection;
+
+        Ok(())
+    }
+
+    fn selection(&self) -> DeviceSelection {
+        self.selection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 
-        op_sender.send(event).expect("send op");
+    fn send(&self, control: Control) {
+        if self.control.send(control).is_err() {
+            log::error!("processor is gone");
+        }
+
+        self.processor.unpark();
+    }
+}
+
+fn listed(id: &str, devices: &[AudioDevice]) -> Result<()> {
+    if devices.iter().any(|device| device.id == id) {
+        Ok(())
+    } else {
+        Err(PlayError::DeviceUnavailable(format!("no device {id}")).into())
+    }
+}
+
+/// Reports the outcome of a request, the shell may have stopped listening
+fn respond(sender: &UnboundedSender<PlayOperationOutput>, output: PlayOperationOutput) {
+    if let PlayOperationOutput::Failure(e) = &output {
+        log::error!("request failed: {e}");
+    }
+
+    if sender.unbounded_send(output).is_err() {
+        log::debug!("request receiver is gone");
+    }
+}
+
+/// A backend error as the shell sees it, the device being unavailable
+/// unless the backend says otherwise
+fn device_error(e: anyhow::Error) -> PlayError {
+    e.downcast::<PlayError>()
+        .unwrap_or_else(|e| PlayError::DeviceUnavailable(e.to_string()))
+}
+
+/// Runs the core on its own thread, taking operations as they come
+/// and input from the rings a block at a time, parked while there's neither
+fn process(control: Receiver<Control>) {
+    let core = Core::new::<RedSirenAUCapabilities>();
+    let mut ports: Option<CorePorts> = None;
+    let mut capture: Option<CaptureSender> = None;
+    // the core lets go of the block by the time it's done with it, so it's refilled in place
+    let mut input = Arc::new(vec![vec![0_f32; BLOCK]]);
+
+    loop {
+        match control.try_recv() {
+            Ok(Control::Attach(new_ports)) => {
+                log::debug!("processor attached with {} channels", new_ports.channels);
+                _ = ports.insert(new_ports);
+                continue;
+            }
+            Ok(Control::Capture(sender)) => {
+                _ = capture.insert(sender);
+                continue;
+            }
+            Ok(Control::Op(op, resolve)) => {
+                // only rendered input goes to the device
+                run(&core, op, resolve.as_ref(), None, &mut capture);
+                continue;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => break,
+        }
+
+        if let Some(ports) = ports.as_mut() {
+            while ports.read_input(Arc::make_mut(&mut input)[0].as_mut_slice()) {
+                log::trace!("processor process block");
+                let op = PlayOperation::Input(input.clone());
+                run(&core, op, None, Some(&mut *ports), &mut capture);
+            }
+
+            let xruns = ports.take_xruns();
+            if xruns > 0 {
+                log::warn!("{xruns} blocks dropped or underrun");
+            }
+        }
+
+        // woken by the input callback or a control message,
+        // either of which arriving since the last look returns at once
+        thread::park_timeout(IDLE_WAIT);
+    }
+
+    log::debug!("processor exited");
+}
+
+fn run(
+    core: &Core,
+    op: PlayOperation,
+    resolve: Option<&UnboundedSender<PlayOperationOutput>>,
+    mut ports: Option<&mut CorePorts>,
+    capture: &mut Option<CaptureSender>,
+) {
+    for effect in core.process_event(op) {
+        match effect {
+            Effect::Render(_) => {
+                if let Some(ports) = ports.as_mut() {
+                    // the view shares the rendered block and lets go of it right after
+                    ports.write_output(core.view().0.as_slice());
+                }
+            }
+            Effect::Resolve(op) => match resolve {
+                Some(resolve) => {
+                    _ = resolve.unbounded_send(op.operation);
+                }
+                None => log::warn!("unrequested resolve {:?}", op.operation),
+            },
+            Effect::Capture(d) => {
+                if let Some(sender) = capture.as_mut() {
+                    if !sender.send(&d.operation) {
+                        log::debug!("capture receiver is gone");
+                        _ = capture.take();
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -83,22 +248,13 @@ cfg_if::cfg_if! {
         mod ios_coreaudio;
     } 
     else {
-        impl StreamerUnit for CoreStreamer {
-            fn init(&self) -> Result<()> {
-                unimplemented!()
-            }
-            fn pause(&self) -> Result<()> {
-                unimplemented!()
-            }
-            fn start(&self) -> Result<()> {
-                unimplemented!()
-            }
-        }
+        mod desktop_headless;
+        pub use desktop_headless::{configure_headless, HeadlessConfig, HeadlessSource, Pacing};
     }
 }
 
 pub struct AUCoreBridge {
-    core: Arc<Mutex<CoreStreamer>>,
+    streamer: CoreStreamer,
     pool: ThreadPool,
 }
 
@@ -111,131 +267,101 @@ impl Default for AUCoreBridge {
 impl AUCoreBridge {
     pub fn new() -> Self {
         let pool = ThreadPool::new().expect("create a thread pool for updates");
-        let (core_streamer, _) = CoreStreamer::new();
-        let CoreStreamer {
-            op_receiver,
-            resolve_sender,
-            render_sender,
-            input_receiver,
-            ..
-        } = core_streamer.clone();
-
-        let core = CORE.clone();
-        pool.spawn(async move {
-            let input_receiver = input_receiver.lock().expect("input receiver lock");
-
-            while let Ok(input) = input_receiver.recv() {
-                let core = core.lock().expect("tick core lock");
-                let render_sender = render_sender.lock().expect("render lock");
-                let op_receiver = op_receiver.lock().expect("op receiver lock");
-                let resolve_sender = resolve_sender.lock().expect("resolve sender lock");
-                let mut ops = vec![PlayOperation::Input(input)];
-
-                match op_receiver.try_recv() {
-                    Ok(op) => {
-                        ops.push(op);
-                    }
-                    Err(TryRecvError::Empty) => {}
-                    Err(e) => {
-                        log::error!("op recv error: {e:?}");
-                    }
-                };
-
-                log::trace!("processor process events");
-
-                for op in ops {
-                    for effect in core.process_event(op) {
-                        match effect {
-                            Effect::Render(_) => {
-                                let view = core.view();
-                                render_sender.send(view).expect("send render");
-                            }
-                            Effect::Resolve(op) => resolve_sender
-                                .unbounded_send(op.operation)
-                                .expect("send resolve"),
-                            Effect::Capture(d) => {
-                                todo!()
-                            }
-                        }
-                    }
-                }
+        let (control, control_receiver) = channel::<Control>();
 
-                log::trace!("processor tick");
-            }
+        let processor = thread::Builder::new()
+            .name("aucore processor".to_string())
+            .spawn(move || process(control_receiver))
+            .expect("process handle");
 
-            log::debug!("processor job exited");
-        })
-        .expect("process handle");
+        let streamer = CoreStreamer::new(control, processor.thread().clone());
 
-        AUCoreBridge {
-            pool,
-            core: Arc::new(Mutex::new(core_streamer)),
-        }
+        AUCoreBridge { pool, streamer }
+    }
+
+    /// Streams capture data to the shell, replacing any earlier receiver
+    pub fn capture(&self) -> CaptureReceiver {
+        let (sender, receiver) = capture_channel();
+        self.streamer.send(Control::Capture(sender));
+
+        receiver
     }
 
     pub fn request(&self, bytes: Vec<u8>) -> UnboundedReceiver<Vec<u8>> {
         let (s_id, mut r_id) = unbounded::<PlayOperationOutput>();
 
-        let event =
-            bincode::deserialize::<PlayOperation>(bytes.as_slice()).expect("deserialize op");
-
-        log::trace!("request {event:?}");
-        
-        let core = self.core.clone();
+        let core = self.streamer.clone();
 
         let tx_bridge = async move {
-            let core = core.lock().expect("lock core");
+            let event = match bincode::deserialize::<PlayOperation>(bytes.as_slice()) {
+                Ok(event) => event,
+                Err(e) => {
+                    let e = PlayError::ProtocolMismatch(e.to_string());
+                    respond(&s_id, PlayOperationOutput::Failure(e));
+                    return;
+                }
+            };
+
+            log::trace!("request {event:?}");
+
             match &event {
-                PlayOperation::InstallAU => match core.init() {
-                    Ok(_) => {
-                        log::info!("init au");
-                        s_id.unbounded_send(PlayOperationOutput::Success)
-                            .expect("receiver is gone");
-                    }
-                    Err(e) => {
-                        log::error!("resume error {e:?}");
-                        s_id.unbounded_send(PlayOperationOutput::Failure)
-                            .expect("receiver is gone");
-                    }
-                },
-                PlayOperation::Resume => match core.start() {
-                    Ok(_) => {
-                        log::info!("playing");
-                        s_id.unbounded_send(PlayOperationOutput::Success)
-                            .expect("receiver is gone");
-                    }
-                    Err(e) => {
-                        log::error!("resume error {e:?}");
-                        s_id.unbounded_send(PlayOperationOutput::Failure)
-                            .expect("receiver is gone");
-                    }
-                },
-                PlayOperation::Suspend => match core.pause() {
-                    Ok(_) => {
-                        log::info!("paused");
-                    }
-                    Err(e) => {
-                        log::error!("suspend error {e:?}");
-                    }
-                },
+                PlayOperation::InstallAU => {
+                    let installed = core.init().map(|(sample_rate, channels)| {
+                        log::info!("init au at {sample_rate} with {channels} channels");
+                        core.send(Control::Op(PlayOperation::SampleRate(sample_rate), None));
+                        core.send(Control::Op(PlayOperation::OutputChannels(channels), None));
+                    });
+                    respond(&s_id, installed.map_err(device_error).into());
+                }
+                PlayOperation::Resume => {
+                    let playing = core.start().map(|_| log::info!("playing"));
+                    respond(&s_id, playing.map_err(device_error).into());
+                }
+                PlayOperation::QueryInputDevices => {
+                    respond(&s_id, core.input_devices().map_err(device_error).into());
+                }
+                PlayOperation::QueryOutputDevices => {
+                    respond(&s_id, core.output_devices().map_err(device_error).into());
+                }
+                PlayOperation::SelectDevices(selection) => {
+                    let selected = core.select(selection.clone()).map(|_| {
+                        log::info!("selected {selection:?}, opened on the next install");
+                    });
+                    respond(&s_id, selected.map_err(device_error).into());
+                }
+                PlayOperation::Suspend => {
+                    let paused = core.pause().map(|_| log::info!("paused"));
+                    respond(&s_id, paused.map_err(device_error).into());
+                }
                 _ => core.forward(event, s_id),
             }
         };
 
-        self.pool.spawn(tx_bridge).expect("spawn bridge");
+        if let Err(e) = self.pool.spawn(tx_bridge) {
+            log::error!("spawn bridge: {e:?}");
+        }
 
         let (sx, rx) = unbounded();
 
         let cx_future = async move {
             while let Some(d) = r_id.next().await {
                 log::trace!("send play op output");
-                sx.unbounded_send(bincode::serialize(&d).expect("serialize output"))
-                    .expect("send msg");
+                match bincode::serialize(&d) {
+                    Ok(bytes) => {
+                        if sx.unbounded_send(bytes).is_err() {
+                            log::debug!("request receiver is gone");
+                            break;
+                        }
+                    }
+                    Err(e) => log::error!("serialize output: {e:?}"),
+                }
             }
             log::debug!("request receive complete");
         };
 
-        self.pool.spawn(cx_future).expect("spawn convert");
+        if let Err(e) = self.pool.spawn(cx_future) {
+            log::error!("spawn convert: {e:?}");
+        }
 
         rx
     }
@@ -1,28 +1,94 @@
-use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use anyhow::anyhow;
 use lazy_static::lazy_static;
 use oboe::{
-    AudioInputCallback, AudioInputStreamSafe, AudioOutputCallback, AudioOutputStream,
-    AudioOutputStreamSafe, AudioStream, AudioStreamAsync, AudioStreamBuilder, AudioStreamSafe,
+    AudioDeviceDirection, AudioDeviceInfo, AudioInputCallback, AudioInputStreamSafe,
+    AudioOutputCallback, AudioOutputStream, AudioOutputStreamSafe, AudioStream,
+    AudioStreamAsync, AudioStreamBase, AudioStreamBuilder, AudioStreamSafe,
     ContentType, DataCallbackResult, Error, Input, InputPreset, IsFrameType, Mono, Output,
     PerformanceMode, SharingMode, Stereo, StreamState, Usage,
 };
 
-use app_core::play::PlayOperationOutput;
-
-use crate::system::SAMPLE_RATE;
+use app_core::play::{AudioDevice, PlayError};
 
+use super::ports::{ports, InputPort, OutputPort};
 use super::CoreStreamer;
 
+/// oboe frames are typed as mono or stereo only, so output streams carry at most two channels
+const OUTPUT_CHANNELS: usize = 2;
+
 lazy_static! {
-    static ref OUT_STREAM: Arc<Mutex<Option<AudioStreamAsync<Output, CoreStreamer>>>> =
+    static ref OUT_STREAM: Arc<Mutex<Option<AudioStreamAsync<Output, OutputCallback>>>> =
         Arc::new(Mutex::new(None));
-    static ref IN_STREAM: Arc<Mutex<Option<AudioStreamAsync<Input, CoreStreamer>>>> =
+    static ref IN_STREAM: Arc<Mutex<Option<AudioStreamAsync<Input, InputCallback>>>> =
         Arc::new(Mutex::new(None));
 }
-impl AudioInputCallback for CoreStreamer {
+
+/// A stream error as the shell sees it
+fn stream_error(error: Error) -> PlayError {
+    match error {
+        Error::Disconnected => PlayError::StreamDisconnected,
+        error => PlayError::DeviceUnavailable(format!("{error:?}")),
+    }
+}
+
+/// Devices the system lists in one direction, as the shell sees them
+fn devices(direction: AudioDeviceDirection, channels: usize) -> anyhow::Result<Vec<AudioDevice>> {
+    let devices = AudioDeviceInfo::request(direction).map_err(|e| anyhow!("{e}"))?;
+
+    Ok(devices
+        .into_iter()
+        .map(|info| AudioDevice {
+            id: info.id.to_string(),
+            name: format!("{} ({:?})", info.product_name, info.device_type),
+            // no channel counts or rates listed means any will do
+            channels: info
+                .channel_counts
+                .iter()
+                .max()
+                .map_or(channels, |count| (*count).max(1) as usize),
+            sample_rates: info.sample_rates.iter().map(|rate| *rate as f64).collect(),
+        })
+        .collect())
+}
+
+/// Oboe id of a selected device, `0` leaving the choice to the system
+fn device_id(selected: Option<String>) -> Result<i32, PlayError> {
+    selected.map_or(Ok(0), |id| {
+        id.parse()
+            .map_err(|_| PlayError::DeviceUnavailable(format!("no device {id}")))
+    })
+}
+
+/// Fails for a selected output with more channels than an oboe stream carries,
+/// rather than folding them to stereo
+fn check_output_channels(selected: Option<&String>) -> anyhow::Result<()> {
+    let Some(id) = selected else {
+        return Ok(());
+    };
+
+    match devices(AudioDeviceDirection::Outputs, OUTPUT_CHANNELS)?
+        .into_iter()
+        .find(|device| &device.id == id)
+    {
+        Some(device) if device.channels > OUTPUT_CHANNELS => {
+            Err(PlayError::DeviceUnavailable(format!(
+                "{} has {} channels, streams carry at most {OUTPUT_CHANNELS}",
+                device.name, device.channels
+            ))
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+struct InputCallback {
+    port: InputPort,
+    streamer: CoreStreamer,
+}
+
+impl AudioInputCallback for InputCallback {
     type FrameType = (f32, Mono);
 
     fn on_error_before_close(
@@ -31,12 +97,7 @@ impl AudioInputCallback for CoreStreamer {
         error: Error,
     ) {
         log::error!("{error:?}");
-        let rs = self.resolve_sender
-            .lock()
-            .expect("lock resolve");
-
-            rs.unbounded_send(PlayOperationOutput::Failure)
-            .expect("send error");
+        self.streamer.fail(stream_error(error));
     }
 
     fn on_error_after_close(&mut self, _audio_stream: &mut dyn AudioInputStreamSafe, error: Error) {
@@ -48,22 +109,17 @@ impl AudioInputCallback for CoreStreamer {
         _: &mut dyn AudioInputStreamSafe,
         frames: &[<Self::FrameType as IsFrameType>::Type],
     ) -> DataCallbackResult {
-        let input: Vec<Vec<f32>> = vec![Vec::from(frames)];
-        let input_sender = self.input_sender.lock().expect("lock input");
-        match input_sender.send(input) {
-            Ok(_) => {
-                log::trace!("sending input");
-                DataCallbackResult::Continue
-            },
-            Err(e) => {
-                log::error!("send input: {e:?}");
-                DataCallbackResult::Stop
-            }
-        }
+        self.port.write(frames);
+        DataCallbackResult::Continue
     }
 }
 
-impl AudioOutputCallback for CoreStreamer {
+struct OutputCallback {
+    port: OutputPort,
+    streamer: CoreStreamer,
+}
+
+impl AudioOutputCallback for OutputCallback {
     type FrameType = (f32, Stereo);
 
     fn on_error_before_close(
@@ -72,13 +128,7 @@ impl AudioOutputCallback for CoreStreamer {
         error: Error,
     ) {
         log::error!("{error:?}");
-        let rs = self
-            .resolve_sender
-            .lock()
-            .expect("resolve lock");
-
-        rs.unbounded_send(PlayOperationOutput::Failure)
-            .expect("send error")
+        self.streamer.fail(stream_error(error));
     }
 
     fn on_error_after_close(
@@ -94,42 +144,24 @@ impl AudioOutputCallback for CoreStreamer {
         _: &mut dyn AudioOutputStreamSafe,
         frames: &mut [(f32, f32)],
     ) -> DataCallbackResult {
-        let render = self.render_receiver.lock().expect("render lock");
-        match render.try_recv() {
-            Ok(vm) => {
-                let ch1 = vm.0.first();
-                let ch1 = ch1.iter().flat_map(|v| *v).into_iter();
-                let ch2 = vm.0.get(1).or_else(|| vm.0.first());
-                let ch2 = ch2.iter().flat_map(|v| *v).into_iter();
-
-                for (frame, vm) in frames.iter_mut().zip(ch1.zip(ch2)) {
-                    *frame = (*vm.0, *vm.1);
-                }
-
-                DataCallbackResult::Continue
-            }
-            Err(TryRecvError::Empty) => DataCallbackResult::Continue,
-            Err(e) => {
-                log::error!("receiver error: {e:?}");
-                DataCallbackResult::Stop
+        self.port.read(frames.len(), |i, ch, sample| {
+            if ch == 0 {
+                frames[i].0 = sample;
+            } else {
+                frames[i].1 = sample;
             }
-        }
+        });
+
+        DataCallbackResult::Continue
     }
 }
 
 impl super::StreamerUnit for CoreStreamer {
-    fn init(&self) -> anyhow::Result<()> {
-        let in_stream = AudioStreamBuilder::default()
-            .set_performance_mode(PerformanceMode::LowLatency)
-            .set_format::<f32>()
-            .set_channel_count::<Mono>()
-            .set_direction::<Input>()
-            .set_input_preset(InputPreset::Unprocessed)
-            .set_frames_per_callback(256)
-            .set_sample_rate(SAMPLE_RATE as i32)
-            .set_callback(self.clone())
-            .open_stream()
-            .expect("create input stream");
+    fn init(&self) -> anyhow::Result<(f64, usize)> {
+        let selection = self.selection();
+        check_output_channels(selection.output.as_ref())?;
+
+        let (input, output, core) = ports(OUTPUT_CHANNELS, self.processor.clone());
 
         let out_stream = AudioStreamBuilder::default()
             .set_performance_mode(PerformanceMode::LowLatency)
@@ -139,16 +171,46 @@ impl super::StreamerUnit for CoreStreamer {
             .set_frames_per_callback(256)
             .set_usage(Usage::Game)
             .set_content_type(ContentType::Music)
-            .set_sample_rate(SAMPLE_RATE as i32)
-            .set_callback(self.clone())
+            .set_device_id(device_id(selection.output)?)
+            .set_callback(OutputCallback {
+                port: output,
+                streamer: self.clone(),
+            })
             .open_stream()
-            .expect("create output stream");
+            .map_err(|e| PlayError::DeviceUnavailable(format!("output stream: {e:?}")))?;
 
-        _ = IN_STREAM.lock().expect("stream lock").insert(in_stream);
+        let sample_rate = out_stream.get_sample_rate();
+        log::debug!("sample_rate: {sample_rate}");
 
-        _ = OUT_STREAM.lock().expect("stream lock").insert(out_stream);
+        let in_stream = AudioStreamBuilder::default()
+            .set_performance_mode(PerformanceMode::LowLatency)
+            .set_format::<f32>()
+            .set_channel_count::<Mono>()
+            .set_direction::<Input>()
+            .set_input_preset(InputPreset::Unprocessed)
+            .set_frames_per_callback(256)
+            .set_sample_rate(sample_rate)
+            .set_device_id(device_id(selection.input)?)
+            .set_callback(InputCallback {
+                port: input,
+                streamer: self.clone(),
+            })
+            .open_stream()
+            .map_err(|e| PlayError::DeviceUnavailable(format!("input stream: {e:?}")))?;
 
-        Ok(())
+        _ = IN_STREAM
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(in_stream);
+
+        _ = OUT_STREAM
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(out_stream);
+
+        self.attach(core);
+
+        Ok((sample_rate as f64, OUTPUT_CHANNELS))
     }
 
     fn pause(&self) -> anyhow::Result<()> {
@@ -168,7 +230,7 @@ impl super::StreamerUnit for CoreStreamer {
     }
 
     fn start(&self) -> anyhow::Result<()> {
-        let mut stream = IN_STREAM.lock().expect("already busy");
+        let mut stream = IN_STREAM.lock().unwrap_or_else(PoisonError::into_inner);
         let stream = stream.as_mut().ok_or(anyhow!("no stream"))?;
 
         match stream.get_state() {
@@ -176,12 +238,12 @@ impl super::StreamerUnit for CoreStreamer {
                 stream.start()?;
             }
             StreamState::Disconnected => {
-                return Err(anyhow!("input stream gone"));
+                return Err(PlayError::StreamDisconnected.into());
             }
             _ => {}
         };
 
-        let mut stream = OUT_STREAM.lock().expect("already busy");
+        let mut stream = OUT_STREAM.lock().unwrap_or_else(PoisonError::into_inner);
         let stream = stream.as_mut().ok_or(anyhow!("no stream"))?;
 
         stream.start()?;
@@ -190,4 +252,12 @@ impl super::StreamerUnit for CoreStreamer {
 
         Ok(())
     }
+
+    fn input_devices(&self) -> anyhow::Result<Vec<AudioDevice>> {
+        devices(AudioDeviceDirection::Inputs, 1)
+    }
+
+    fn output_devices(&self) -> anyhow::Result<Vec<AudioDevice>> {
+        devices(AudioDeviceDirection::Outputs, OUTPUT_CHANNELS)
+    }
 }
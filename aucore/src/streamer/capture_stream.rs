@@ -0,0 +1,103 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc::{
+    channel, unbounded, Receiver, Sender, UnboundedReceiver, UnboundedSender,
+};
+use futures::stream::{select, Select};
+use futures::{Stream, StreamExt};
+
+use app_core::play::CaptureOutput;
+
+/// continuous captures queued for the shell before newer ones are dropped
+pub const CAPTURE_BACKLOG: usize = 8;
+
+/// Serialized `CaptureOutput` for the shell
+pub struct CaptureReceiver(Select<Receiver<Vec<u8>>, UnboundedReceiver<Vec<u8>>>);
+
+impl Stream for CaptureReceiver {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_unpin(cx)
+    }
+}
+
+/// Core end of the capture stream, continuous data is bounded
+/// while one-off results are always delivered
+pub struct CaptureSender {
+    frames: Sender<Vec<u8>>,
+    results: UnboundedSender<Vec<u8>>,
+}
+
+pub fn capture_channel() -> (CaptureSender, CaptureReceiver) {
+    let (frames, frames_receiver) = channel(CAPTURE_BACKLOG);
+    let (results, results_receiver) = unbounded();
+
+    (
+        CaptureSender { frames, results },
+        CaptureReceiver(select(frames_receiver, results_receiver)),
+    )
+}
+
+impl CaptureSender {
+    /// Queues a capture for the shell, `false` once it has stopped listening
+    pub fn send(&mut self, output: &CaptureOutput) -> bool {
+        let bytes = match bincode::serialize(output) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::error!("serialize capture: {e:?}");
+                return true;
+            }
+        };
+
+        match output {
+            CaptureOutput::CaptureRecording(_)
+            | CaptureOutput::CaptureNoiseFloor(_)
+            | CaptureOutput::CaptureLatency(_) => self.results.unbounded_send(bytes).is_ok(),
+            _ => match self.frames.try_send(bytes) {
+                Ok(_) => true,
+                Err(e) if e.is_full() => {
+                    log::trace!("shell is behind, capture dropped");
+                    true
+                }
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn drops_frames_but_not_results() {
+        let (mut sender, mut receiver) = capture_channel();
+
+        for _ in 0..CAPTURE_BACKLOG * 4 {
+            assert!(sender.send(&CaptureOutput::CaptureData(vec![0.5; 64])));
+        }
+        assert!(sender.send(&CaptureOutput::CaptureLatency(None)));
+        drop(sender);
+
+        let received = block_on(receiver.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .map(|bytes| bincode::deserialize::<CaptureOutput>(bytes.as_slice()).expect("capture"))
+            .collect::<Vec<_>>();
+
+        // one slot more than the backlog for the sender itself
+        assert_eq!(received.len(), CAPTURE_BACKLOG + 2);
+        assert!(received.contains(&CaptureOutput::CaptureLatency(None)));
+    }
+
+    #[test]
+    fn notices_the_shell_leaving() {
+        let (mut sender, receiver) = capture_channel();
+        drop(receiver);
+
+        assert!(!sender.send(&CaptureOutput::CaptureData(vec![])));
+        assert!(!sender.send(&CaptureOutput::CaptureNoiseFloor(vec![])));
+    }
+}
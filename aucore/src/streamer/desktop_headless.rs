@@ -0,0 +1,595 @@
+use std::f32::consts::TAU;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use lazy_static::lazy_static;
+
+use app_core::play::{AudioDevice, PlayError};
+
+use super::ports::{ports, InputPort, OutputPort};
+use super::CoreStreamer;
+use crate::system::DEFAULT_SAMPLE_RATE;
+
+/// longest wait for the core to render a block when running as fast as possible
+const FAST_RENDER_WAIT: Duration = Duration::from_millis(100);
+/// how long the stream thread sleeps between looks at what the core has rendered
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// id of the only input, whatever the source
+const INPUT_ID: &str = "headless-input";
+/// id of the only output, whatever the sink
+const OUTPUT_ID: &str = "headless-output";
+
+/// Where the input frames come from
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeadlessSource {
+    Silence,
+    /// mono mix of a WAV file, the stream ends with it
+    Wav(PathBuf),
+    Sine {
+        freq: f32,
+        amp: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// a block per block duration, as a device would
+    RealTime,
+    /// the next block as soon as the core has rendered the last
+    Fast,
+}
+
+/// Streams of the headless backend, configured before `InstallAU`
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessConfig {
+    pub source: HeadlessSource,
+    /// WAV file the output is written to
+    pub sink: Option<PathBuf>,
+    pub pacing: Pacing,
+    /// sample rate of generated input, a WAV source brings its own
+    pub sample_rate: f64,
+    pub channels: usize,
+    /// frames per block
+    pub block: usize,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        Self {
+            source: HeadlessSource::Silence,
+            sink: None,
+            pacing: Pacing::RealTime,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            channels: 2,
+            block: 256,
+        }
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: Mutex<HeadlessConfig> = Mutex::new(HeadlessConfig::default());
+    static ref STREAM: Arc<Mutex<Option<HeadlessStream>>> = Default::default();
+}
+
+/// Sets up the streams opened by the next `InstallAU`
+pub fn configure_headless(config: HeadlessConfig) {
+    *CONFIG.lock().unwrap_or_else(PoisonError::into_inner) = config;
+}
+
+struct Input {
+    source: HeadlessSource,
+    samples: Vec<f32>,
+    position: usize,
+    sample_rate: f64,
+}
+
+impl Input {
+    fn open(source: &HeadlessSource, sample_rate: f64) -> Result<Self> {
+        let (samples, sample_rate) = match source {
+            HeadlessSource::Wav(path) => read_wav(path)?,
+            _ => (vec![], sample_rate),
+        };
+
+        Ok(Self {
+            source: source.clone(),
+            samples,
+            position: 0,
+            sample_rate,
+        })
+    }
+
+    /// Fills the next block, `false` once a file has ended
+    fn next_block(&mut self, block: &mut [f32]) -> bool {
+        let from = self.position;
+        self.position += block.len();
+
+        match &self.source {
+            HeadlessSource::Silence => block.fill(0.0),
+            HeadlessSource::Sine { freq, amp } => {
+                for (i, sample) in (from..).zip(block.iter_mut()) {
+                    *sample = amp * (TAU * freq * i as f32 / self.sample_rate as f32).sin();
+                }
+            }
+            HeadlessSource::Wav(_) if from >= self.samples.len() => return false,
+            HeadlessSource::Wav(_) => {
+                let to = self.samples.len().min(from + block.len());
+                block[..to - from].copy_from_slice(&self.samples[from..to]);
+                block[to - from..].fill(0.0);
+            }
+        }
+
+        true
+    }
+
+    fn ended(&self) -> bool {
+        matches!(self.source, HeadlessSource::Wav(_)) && self.position >= self.samples.len()
+    }
+}
+
+impl HeadlessConfig {
+    fn input_device(&self) -> Result<AudioDevice> {
+        let (name, sample_rate) = match &self.source {
+            HeadlessSource::Silence => ("silence".to_string(), self.sample_rate),
+            HeadlessSource::Sine { freq, .. } => (format!("{freq} Hz sine"), self.sample_rate),
+            HeadlessSource::Wav(path) => (
+                path.display().to_string(),
+                WavReader::open(path)?.spec().sample_rate as f64,
+            ),
+        };
+
+        Ok(AudioDevice {
+            id: INPUT_ID.to_string(),
+            name,
+            channels: 1,
+            sample_rates: vec![sample_rate],
+        })
+    }
+
+    fn output_device(&self) -> AudioDevice {
+        AudioDevice {
+            id: OUTPUT_ID.to_string(),
+            name: self
+                .sink
+                .as_ref()
+                .map_or("discarded".to_string(), |path| path.display().to_string()),
+            channels: self.channels.max(1),
+            sample_rates: vec![self.sample_rate],
+        }
+    }
+}
+
+/// Fails on any device but the configured ones
+fn check_selected(selected: Option<String>, id: &str) -> Result<()> {
+    match selected {
+        Some(selected) if selected != id => {
+            Err(PlayError::DeviceUnavailable(format!("no device {selected}")).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Mono mix of a WAV file as floats, and its sample rate
+fn read_wav(path: &Path) -> Result<(Vec<f32>, f64)> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let interleaved = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok((samples, spec.sample_rate as f64))
+}
+
+type Sink = WavWriter<BufWriter<File>>;
+
+/// What a device callback would own, handed to the stream thread while it runs
+struct Device {
+    input: Input,
+    sink: Option<Sink>,
+    input_port: InputPort,
+    output_port: OutputPort,
+}
+
+struct HeadlessStream {
+    device: Option<Device>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Device>>,
+    pacing: Pacing,
+    block: usize,
+}
+
+impl HeadlessStream {
+    /// Waits for the stream thread to hand the device back
+    fn join(&mut self) -> Result<()> {
+        if let Some(thread) = self.thread.take() {
+            let device = thread
+                .join()
+                .map_err(|_| anyhow!("stream thread panicked"))?;
+            _ = self.device.insert(device);
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        self.join()?;
+
+        if let Some(sink) = self.device.as_mut().and_then(|d| d.sink.as_mut()) {
+            sink.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Feeds input blocks to the core and writes whatever it renders, until paused or the input ends,
+/// failing the latest request when it does as a disconnected device would
+fn run(
+    mut device: Device,
+    running: Arc<AtomicBool>,
+    pacing: Pacing,
+    block: usize,
+    streamer: CoreStreamer,
+) -> Device {
+    let channels = device.output_port.channels;
+    let block_duration = Duration::from_secs_f64(block as f64 / device.input.sample_rate);
+    let mut frames = vec![0_f32; block];
+    let mut rendered = vec![0_f32; block * channels];
+    let mut next = Instant::now();
+
+    while running.load(Ordering::SeqCst) {
+        if !device.input.next_block(frames.as_mut_slice()) {
+            log::info!("headless input ended");
+            running.store(false, Ordering::SeqCst);
+            streamer.fail(PlayError::StreamDisconnected);
+            break;
+        }
+
+        device.input_port.write(frames.as_slice());
+
+        let ready = match pacing {
+            Pacing::Fast => {
+                let started = Instant::now();
+                while device.output_port.frames() < block && started.elapsed() < FAST_RENDER_WAIT {
+                    thread::sleep(POLL_INTERVAL);
+                }
+
+                device.output_port.frames()
+            }
+            Pacing::RealTime => {
+                next += block_duration;
+                if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+
+                block
+            }
+        };
+
+        rendered.resize(ready * channels, 0.0);
+        device
+            .output_port
+            .read(ready, |i, ch, sample| rendered[i * channels + ch] = sample);
+
+        if let Some(sink) = device.sink.as_mut() {
+            for sample in rendered.iter() {
+                if let Err(e) = sink.write_sample(*sample) {
+                    log::error!("write output: {e:?}");
+                    break;
+                }
+            }
+        }
+    }
+
+    log::debug!("headless stream exited");
+
+    device
+}
+
+impl super::StreamerUnit for CoreStreamer {
+    fn init(&self) -> Result<(f64, usize)> {
+        let config = CONFIG
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        let selection = self.selection();
+        check_selected(selection.input, INPUT_ID)?;
+        check_selected(selection.output, OUTPUT_ID)?;
+
+        let mut stream = STREAM.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(mut old) = stream.take() {
+            old.stop()?;
+        }
+
+        let input = Input::open(&config.source, config.sample_rate)?;
+        let sample_rate = input.sample_rate;
+        let channels = config.channels.max(1);
+
+        let sink = config
+            .sink
+            .as_ref()
+            .map(|path| {
+                WavWriter::create(
+                    path,
+                    WavSpec {
+                        channels: channels as u16,
+                        sample_rate: sample_rate as u32,
+                        bits_per_sample: 32,
+                        sample_format: SampleFormat::Float,
+                    },
+                )
+            })
+            .transpose()?;
+
+        log::debug!("sample_rate: {sample_rate}, channels: {channels}");
+
+        let (input_port, output_port, core) = ports(channels, self.processor.clone());
+
+        _ = stream.insert(HeadlessStream {
+            device: Some(Device {
+                input,
+                sink,
+                input_port,
+                output_port,
+            }),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            pacing: config.pacing,
+            block: config.block.max(1),
+        });
+
+        self.attach(core);
+
+        Ok((sample_rate, channels))
+    }
+
+    fn pause(&self) -> Result<()> {
+        let mut stream = STREAM.lock().unwrap_or_else(PoisonError::into_inner);
+        let stream = stream.as_mut().ok_or(anyhow!("no stream"))?;
+
+        stream.stop()?;
+
+        log::info!("pausing");
+
+        Ok(())
+    }
+
+    fn start(&self) -> Result<()> {
+        let mut stream = STREAM.lock().unwrap_or_else(PoisonError::into_inner);
+        let stream = stream.as_mut().ok_or(anyhow!("no stream"))?;
+
+        if stream.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        stream.join()?;
+
+        let device = stream.device.take().ok_or(anyhow!("no device"))?;
+        if device.input.ended() {
+            _ = stream.device.insert(device);
+            return Err(PlayError::StreamDisconnected.into());
+        }
+
+        stream.running.store(true, Ordering::SeqCst);
+
+        let running = stream.running.clone();
+        let (pacing, block) = (stream.pacing, stream.block);
+        let streamer = self.clone();
+
+        _ = stream.thread.insert(thread::spawn(move || {
+            run(device, running, pacing, block, streamer)
+        }));
+
+        log::info!("starting");
+
+        Ok(())
+    }
+
+    fn input_devices(&self) -> Result<Vec<AudioDevice>> {
+        let config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+
+        Ok(vec![config.input_device()?])
+    }
+
+    fn output_devices(&self) -> Result<Vec<AudioDevice>> {
+        let config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+
+        Ok(vec![config.output_device()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ports::BLOCK;
+    use super::super::{Control, StreamerUnit};
+    use super::*;
+    use app_core::play::DeviceSelection;
+    use std::sync::mpsc::{channel, Receiver};
+
+    const BLOCKS: usize = 16;
+
+    lazy_static! {
+        /// the backend's config and stream are global, so tests take turns with them
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    /// A streamer whose control messages come to the test, standing in for the processor
+    fn streamer() -> (CoreStreamer, Receiver<Control>) {
+        let (control, control_receiver) = channel();
+
+        (
+            CoreStreamer::new(control, thread::current()),
+            control_receiver,
+        )
+    }
+
+    fn serial() -> std::sync::MutexGuard<'static, ()> {
+        let guard = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        configure_headless(HeadlessConfig::default());
+
+        guard
+    }
+
+    #[test]
+    fn streams_generated_input_to_a_wav_sink() {
+        let _serial = serial();
+        let sink =
+            std::env::temp_dir().join(format!("red-siren-headless-{}.wav", std::process::id()));
+        configure_headless(HeadlessConfig {
+            source: HeadlessSource::Sine {
+                freq: 440.0,
+                amp: 0.5,
+            },
+            sink: Some(sink.clone()),
+            pacing: Pacing::Fast,
+            sample_rate: 48000.0,
+            channels: 2,
+            block: 128,
+        });
+
+        let (streamer, control) = streamer();
+        assert_eq!(streamer.init().expect("init"), (48000.0, 2));
+
+        let Ok(Control::Attach(mut core)) = control.recv() else {
+            panic!("expected the core ports");
+        };
+
+        // stands in for the core, echoing input as output
+        let echo = thread::spawn(move || {
+            let mut block = vec![0_f32; BLOCK];
+            let mut echoed = 0;
+            while echoed < BLOCKS * 128 {
+                if core.read_input(block.as_mut_slice()) {
+                    core.write_output(&[block.clone()]);
+                    echoed += BLOCK;
+                } else {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        });
+
+        streamer.start().expect("start");
+        echo.join().expect("echo");
+        // let the stream pick up the last echoed block
+        thread::sleep(FAST_RENDER_WAIT);
+        streamer.pause().expect("pause");
+
+        let mut reader = WavReader::open(&sink).expect("open sink");
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48000);
+
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("read sink");
+        assert!(samples.len() >= BLOCKS * 128 * 2);
+        assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+
+        let peak = samples.iter().copied().fold(0.0, f32::max);
+        assert!((peak - 0.5).abs() < 0.01, "{peak}");
+
+        _ = std::fs::remove_file(sink);
+    }
+
+    #[test]
+    fn fails_the_latest_request_when_the_wav_ends() {
+        use app_core::play::PlayOperationOutput;
+        use futures::StreamExt;
+
+        let _serial = serial();
+        let input = std::env::temp_dir().join(format!(
+            "red-siren-headless-input-{}.wav",
+            std::process::id()
+        ));
+        {
+            let spec = WavSpec {
+                channels: 1,
+                sample_rate: 48000,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            };
+            let mut writer = WavWriter::create(&input, spec).expect("create input");
+            for _ in 0..64 {
+                writer.write_sample(0.25_f32).expect("write input");
+            }
+            writer.finalize().expect("finalize input");
+        }
+        configure_headless(HeadlessConfig {
+            source: HeadlessSource::Wav(input.clone()),
+            pacing: Pacing::Fast,
+            block: 128,
+            ..Default::default()
+        });
+
+        let (streamer, _control) = streamer();
+        assert_eq!(streamer.init().expect("init").0, 48000.0);
+
+        let (sender, mut receiver) = futures::channel::mpsc::unbounded();
+        *streamer
+            .resolve_sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = sender;
+
+        streamer.start().expect("start");
+
+        assert!(matches!(
+            futures::executor::block_on(receiver.next()),
+            Some(PlayOperationOutput::Failure(PlayError::StreamDisconnected))
+        ));
+
+        streamer.pause().expect("pause");
+        _ = std::fs::remove_file(input);
+    }
+
+    #[test]
+    fn selects_only_its_own_devices() {
+        let _serial = serial();
+        let (streamer, _control) = streamer();
+
+        let inputs = streamer.input_devices().expect("inputs");
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].id, INPUT_ID);
+        assert_eq!(inputs[0].channels, 1);
+
+        let selection = DeviceSelection {
+            input: Some(INPUT_ID.to_string()),
+            output: Some(OUTPUT_ID.to_string()),
+        };
+        streamer.select(selection.clone()).expect("select");
+        assert_eq!(streamer.selection(), selection);
+
+        let error = streamer
+            .select(DeviceSelection {
+                input: None,
+                output: Some("elsewhere".to_string()),
+            })
+            .expect_err("unlisted device");
+        assert!(matches!(
+            error.downcast::<PlayError>(),
+            Ok(PlayError::DeviceUnavailable(_))
+        ));
+        assert_eq!(streamer.selection(), selection);
+    }
+}
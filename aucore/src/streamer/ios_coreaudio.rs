@@ -1,9 +1,8 @@
 extern crate coreaudio;
 
-use std::sync::mpsc::TryRecvError;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use coreaudio::audio_unit::audio_format::LinearPcmFlags;
 use coreaudio::audio_unit::render_callback::{self, data};
 use coreaudio::audio_unit::{AudioUnit, Element, SampleFormat, Scope, StreamFormat};
@@ -12,26 +11,49 @@ use coreaudio::sys::{
 };
 use lazy_static::lazy_static;
 
+use app_core::play::{AudioDevice, PlayError};
+
+use super::ports::{ports, BLOCK};
 use super::CoreStreamer;
 
 type S = f32;
 const SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
+/// frames interleaved out of the ring at a time, longer renders take a few passes
+const MAX_RENDER_FRAMES: usize = BLOCK * 16;
 
 lazy_static! {
     static ref AU_UNIT: Arc<Mutex<Option<AudioUnit>>> = Default::default();
 }
 
+/// RemoteIO plays on whatever route the audio session picks, there are
+/// no devices to list or choose from here
+fn unsupported() -> PlayError {
+    PlayError::Operation("device selection is not supported on iOS".to_string())
+}
+
+fn check_selected(selected: Option<String>) -> Result<()> {
+    match selected {
+        Some(_) => Err(unsupported().into()),
+        None => Ok(()),
+    }
+}
+
 impl super::StreamerUnit for CoreStreamer {
-    fn init(&self) -> Result<()> {
+    fn init(&self) -> Result<(f64, usize)> {
+        let selection = self.selection();
+        check_selected(selection.input)?;
+        check_selected(selection.output)?;
+
         let mut audio_unit = AudioUnit::new(coreaudio::audio_unit::IOType::RemoteIO)?;
 
         let id = kAudioUnitProperty_StreamFormat;
         let asbd: AudioStreamBasicDescription =
             audio_unit.get_property(id, Scope::Output, Element::Output)?;
         let sample_rate = asbd.mSampleRate;
+        let channels = asbd.mChannelsPerFrame.max(1);
 
         audio_unit.uninitialize()?;
-        log::debug!("sample_rate: {sample_rate}");
+        log::debug!("sample_rate: {sample_rate}, channels: {channels}");
 
         configure_for_recording(&mut audio_unit)?;
 
@@ -49,7 +71,7 @@ impl super::StreamerUnit for CoreStreamer {
             sample_rate,
             sample_format: SAMPLE_FORMAT,
             flags: format_flag | LinearPcmFlags::IS_PACKED | LinearPcmFlags::IS_NON_INTERLEAVED,
-            channels: 2,
+            channels,
         };
 
         let in_stream_format = StreamFormat {
@@ -78,73 +100,60 @@ impl super::StreamerUnit for CoreStreamer {
             Some(&stream_format.to_asbd()),
         )?;
 
-        let Self {
-            input_sender,
-            render_receiver,
-            ..
-        } = self.clone();
+        let (mut input, mut output, core) = ports(channels as usize, self.processor.clone());
+        let mut scratch = vec![0_f32; MAX_RENDER_FRAMES * output.channels];
 
         type Args = render_callback::Args<data::NonInterleaved<S>>;
 
         log::debug!("set_input_callback");
         audio_unit.set_input_callback(move |args| {
-            let input_sender = input_sender.lock().expect("input sender lock");
-            let Args {
-                data,
-                num_frames: _,
-                ..
-            } = args;
-            let input: Vec<Vec<f32>> =
-                Vec::from_iter(data.channels().into_iter().map(|s| Vec::from(s)));
-            input_sender.send(input).expect("send input");
+            let Args { data, .. } = args;
+            if let Some(frames) = data.channels().next() {
+                input.write(frames);
+            }
 
             Ok(())
         })?;
 
         log::debug!("set_render_callback");
         audio_unit.set_render_callback(move |args: Args| {
-            let render_receiver = render_receiver.lock().expect("render receiver lock");
             let Args {
                 num_frames,
                 mut data,
                 ..
             } = args;
 
-            match render_receiver.try_recv() {
-                Ok(vm) => {
-                    let buffer = &vm.0;
-                    for i in 0..num_frames {
-                        for (ch, channel) in data.channels_mut().enumerate() {
-                            let sample: &S = buffer
-                                .get(ch)
-                                .or_else(|| buffer.first())
-                                .and_then(|b| b.get(i))
-                                .unwrap_or(&0_f32);
-
-                            channel[i] = *sample * 10.0;
-                        }
-                    }
+            let channels = output.channels;
+            for from in (0..num_frames).step_by(MAX_RENDER_FRAMES) {
+                let frames = MAX_RENDER_FRAMES.min(num_frames - from);
+                output.read(frames, |i, ch, sample| scratch[i * channels + ch] = sample);
 
-                    Ok(())
-                }
-                Err(TryRecvError::Empty) => Ok(()),
-                Err(e) => {
-                    log::error!("recv err: {e:?}");
-                    Err(())
+                for (ch, channel) in data.channels_mut().enumerate() {
+                    let ch = ch.min(channels - 1);
+                    for (i, sample) in channel[from..from + frames].iter_mut().enumerate() {
+                        *sample = scratch[i * channels + ch] * 10.0;
+                    }
                 }
             }
+
+            Ok(())
         })?;
 
         audio_unit.initialize()?;
 
-        _ = AU_UNIT.lock().unwrap().insert(audio_unit);
+        _ = AU_UNIT
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(audio_unit);
 
-        Ok(())
+        self.attach(core);
+
+        Ok((sample_rate, channels as usize))
     }
 
     fn pause(&self) -> Result<()> {
-        let mut input_audio_unit = AU_UNIT.lock().unwrap();
-        let input_audio_unit = input_audio_unit.as_mut().unwrap();
+        let mut input_audio_unit = AU_UNIT.lock().unwrap_or_else(PoisonError::into_inner);
+        let input_audio_unit = input_audio_unit.as_mut().ok_or(anyhow!("no stream"))?;
 
         input_audio_unit.stop()?;
 
@@ -154,8 +163,8 @@ impl super::StreamerUnit for CoreStreamer {
     }
 
     fn start(&self) -> Result<()> {
-        let mut input_audio_unit = AU_UNIT.lock().unwrap();
-        let input_audio_unit = input_audio_unit.as_mut().unwrap();
+        let mut input_audio_unit = AU_UNIT.lock().unwrap_or_else(PoisonError::into_inner);
+        let input_audio_unit = input_audio_unit.as_mut().ok_or(anyhow!("no stream"))?;
 
         input_audio_unit.start()?;
 
@@ -163,6 +172,14 @@ impl super::StreamerUnit for CoreStreamer {
 
         Ok(())
     }
+
+    fn input_devices(&self) -> Result<Vec<AudioDevice>> {
+        Err(unsupported().into())
+    }
+
+    fn output_devices(&self) -> Result<Vec<AudioDevice>> {
+        Err(unsupported().into())
+    }
 }
 
 fn configure_for_recording(audio_unit: &mut AudioUnit) -> Result<(), coreaudio::Error> {
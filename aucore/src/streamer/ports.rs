@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::Thread;
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+/// frames the core processes at a time
+pub const BLOCK: usize = 256;
+/// frames each ring holds, enough to ride out a few slow blocks
+const RING_FRAMES: usize = BLOCK * 8;
+
+/// Device end of the input ring, written by the input callback
+pub struct InputPort {
+    ring: Producer<f32>,
+    xruns: Arc<AtomicUsize>,
+    /// woken once there's input to process
+    processor: Thread,
+}
+
+/// Device end of the output ring, read by the output callback
+pub struct OutputPort {
+    ring: Consumer<f32>,
+    pub channels: usize,
+    xruns: Arc<AtomicUsize>,
+}
+
+/// Core end of both rings, owned by the processing thread
+pub struct CorePorts {
+    input: Consumer<f32>,
+    output: Producer<f32>,
+    pub channels: usize,
+    xruns: Arc<AtomicUsize>,
+}
+
+/// Preallocated rings between the device callbacks and the core,
+/// the output interleaving `channels` and input waking the `processor`
+pub fn ports(channels: usize, processor: Thread) -> (InputPort, OutputPort, CorePorts) {
+    let channels = channels.max(1);
+    let (input_tx, input_rx) = RingBuffer::new(RING_FRAMES);
+    let (output_tx, output_rx) = RingBuffer::new(RING_FRAMES * channels);
+    let xruns = Arc::new(AtomicUsize::new(0));
+
+    (
+        InputPort {
+            ring: input_tx,
+            xruns: xruns.clone(),
+            processor,
+        },
+        OutputPort {
+            ring: output_rx,
+            channels,
+            xruns: xruns.clone(),
+        },
+        CorePorts {
+            input: input_rx,
+            output: output_tx,
+            channels,
+            xruns,
+        },
+    )
+}
+
+impl InputPort {
+    /// Queues captured frames for the core, dropping whatever doesn't fit
+    pub fn write(&mut self, frames: &[f32]) {
+        let fits = frames.len().min(self.ring.slots());
+        if fits < frames.len() {
+            self.xruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Ok(chunk) = self.ring.write_chunk_uninit(fits) {
+            chunk.fill_from_iter(frames.iter().copied());
+        }
+
+        self.processor.unpark();
+    }
+}
+
+impl OutputPort {
+    /// Whole frames rendered and waiting
+    pub fn frames(&self) -> usize {
+        self.ring.slots() / self.channels
+    }
+
+    /// Hands `frames` frames to `write` as frame, channel and sample,
+    /// silence for whatever the core hasn't rendered in time
+    pub fn read(&mut self, frames: usize, mut write: impl FnMut(usize, usize, f32)) {
+        let wanted = frames * self.channels;
+        let ready = self.frames().min(frames) * self.channels;
+        if ready < wanted {
+            self.xruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut i = 0;
+        if let Ok(chunk) = self.ring.read_chunk(ready) {
+            let (head, tail) = chunk.as_slices();
+            for sample in head.iter().chain(tail) {
+                write(i / self.channels, i % self.channels, *sample);
+                i += 1;
+            }
+            chunk.commit_all();
+        }
+
+        for i in i..wanted {
+            write(i / self.channels, i % self.channels, 0.0);
+        }
+    }
+}
+
+impl CorePorts {
+    /// Frames captured and waiting
+    pub fn input_frames(&self) -> usize {
+        self.input.slots()
+    }
+
+    /// Fills `block` with the next input if enough has arrived
+    pub fn read_input(&mut self, block: &mut [f32]) -> bool {
+        let Ok(chunk) = self.input.read_chunk(block.len()) else {
+            return false;
+        };
+
+        let (head, tail) = chunk.as_slices();
+        block[..head.len()].copy_from_slice(head);
+        block[head.len()..].copy_from_slice(tail);
+        chunk.commit_all();
+
+        true
+    }
+
+    /// Queues a rendered block for the device, interleaving its channels
+    /// and repeating the first for any missing, drops frames that don't fit
+    pub fn write_output(&mut self, block: &[Vec<f32>]) {
+        let Some(first) = block.first() else {
+            return;
+        };
+
+        let frames = first.len().min(self.output.slots() / self.channels);
+        if frames < first.len() {
+            self.xruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let channels = self.channels;
+        if let Ok(chunk) = self.output.write_chunk_uninit(frames * channels) {
+            chunk.fill_from_iter((0..frames * channels).map(|i| {
+                let data = block.get(i % channels).unwrap_or(first);
+                data.get(i / channels).copied().unwrap_or_default()
+            }));
+        }
+    }
+
+    /// Overflows and underruns on either side since last asked
+    pub fn take_xruns(&self) -> usize {
+        self.xruns.swap(0, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn carries_blocks_both_ways() {
+        let (mut input, mut output, mut core) = ports(2, thread::current());
+
+        input.write(&[0.5; 100]);
+        let mut block = [0.0; BLOCK];
+        assert!(!core.read_input(&mut block));
+
+        input.write(&[0.25; BLOCK]);
+        assert!(core.read_input(&mut block));
+        assert_eq!(block[99], 0.5);
+        assert_eq!(block[100], 0.25);
+        assert_eq!(core.input_frames(), 100);
+
+        core.write_output(&[vec![1.0; 4], vec![-1.0; 4]]);
+        assert_eq!(output.frames(), 4);
+
+        let mut out = [[9.0; 2]; 6];
+        output.read(6, |frame, ch, sample| out[frame][ch] = sample);
+        assert_eq!(out[0], [1.0, -1.0]);
+        assert_eq!(out[3], [1.0, -1.0]);
+        assert_eq!(out[4], [0.0, 0.0]);
+        assert_eq!(core.take_xruns(), 1);
+        assert_eq!(core.take_xruns(), 0);
+    }
+
+    #[test]
+    fn drops_what_does_not_fit() {
+        let (mut input, _output, mut core) = ports(1, thread::current());
+
+        input.write(vec![0.1; RING_FRAMES + 10].as_slice());
+        assert_eq!(core.input_frames(), RING_FRAMES);
+        assert_eq!(core.take_xruns(), 1);
+
+        core.write_output(&[vec![0.1; RING_FRAMES + 10]]);
+        assert_eq!(core.take_xruns(), 1);
+    }
+
+    #[test]
+    fn streams_across_threads() {
+        let (mut input, _output, mut core) = ports(1, thread::current());
+        let blocks = 64;
+
+        let device = thread::spawn(move || {
+            let mut sent = 0;
+            while sent < blocks * BLOCK {
+                let frames = (sent..sent + 64).map(|i| i as f32).collect::<Vec<_>>();
+                if input.ring.slots() >= frames.len() {
+                    input.write(frames.as_slice());
+                    sent += frames.len();
+                } else {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut block = [0.0; BLOCK];
+        let mut received = 0;
+        while received < blocks * BLOCK {
+            if core.read_input(&mut block) {
+                assert_eq!(block[0], received as f32);
+                assert_eq!(block[BLOCK - 1], (received + BLOCK - 1) as f32);
+                received += BLOCK;
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        device.join().expect("device thread");
+        assert_eq!(core.take_xruns(), 0);
+    }
+}
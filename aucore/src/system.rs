@@ -1,16 +1,35 @@
-use app_core::{
-    instrument::{Config, Node},
-    tuner::TuningValue,
-};
+use std::mem;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+use anyhow::{anyhow, bail, Result};
+use app_core::{instrument::Node, play::FxChain, tuner::TuningValue};
 use fundsp::hacker32::*;
 
-pub const SAMPLE_RATE: f64 = 44100.0;
+use crate::{
+    calibration::{gate_threshold, node_gain},
+    effects,
+    voice::NodeVoice,
+};
+
+pub const DEFAULT_SAMPLE_RATE: f64 = 44100.0;
 const SNOOP_SIZE: usize = 64;
-const CHANNELS: usize = 2;
+pub const DEFAULT_CHANNELS: usize = 2;
 pub const MUL: f32 = 100000.0;
 
 pub struct System {
     pub net_be: BigBlockAdapter32,
+    pub fx_be: BigBlockAdapter32,
+    /// effects chain being built on another thread, swapped in once ready
+    fx_pending: Option<Receiver<BigBlockAdapter32>>,
+    mix_data: Vec<Vec<f32>>,
+    gates: Vec<f32>,
+    gate_data: Vec<Vec<f32>>,
+    /// frame offset into the next block, node index and level
+    gate_changes: Vec<(usize, usize, f32)>,
+    /// slices handed to the backends, emptied after every block and kept for their allocation
+    in_slices: Vec<&'static [f32]>,
+    out_slices: Vec<&'static mut [f32]>,
     pub size: usize,
     pub channels: usize,
     pub sample_rate: f64,
@@ -18,46 +37,54 @@ pub struct System {
     pub node_snp: Vec<(Snoop<f32>, usize)>,
     pub b_centres: Vec<Shared<f32>>,
     pub b_qs: Vec<Shared<f32>>,
+    pub b_gains: Vec<Shared<f32>>,
+    pub b_thresholds: Vec<Shared<f32>>,
+    /// tuning amplitude and measured noise floor of each node,
+    /// the gain and threshold derive from them
+    levels: Vec<(f32, Option<f32>)>,
     pub n_fs: Vec<Shared<f32>>,
+    pub n_pans: Vec<Vec<Shared<f32>>>,
+    pub master: Shared<f32>,
     pub out_snp: Snoop<f32>,
+    /// latest output snoop data
+    pub out_data: Vec<f32>,
+    /// latest snoop data of each node
+    pub node_data: Vec<(usize, Vec<f32>)>,
 }
 
 impl System {
-    pub fn new(nodes_data: &[Node], config: &Config, tuning: &[TuningValue]) -> Self {
-        let sample_rate = SAMPLE_RATE;
-        let channels = Ord::min(config.groups, CHANNELS);
-        let mut net = Net32::new(1, channels);
-        net.set_sample_rate(sample_rate);
+    /// Builds the node graph, failing when there are no nodes
+    /// or they don't pair up with their tuning
+    pub fn new(
+        nodes_data: &[Node],
+        tuning: &[TuningValue],
+        channels: usize,
+        sample_rate: f64,
+        effects: &FxChain,
+    ) -> Result<Self> {
+        check_pairing(nodes_data, tuning)?;
 
+        let channels = channels.max(1);
         let size = nodes_data.len();
+        let mut net = Net32::new(1 + size, channels);
+
         let mut nodes = vec![];
         let mut node_snp = vec![];
         let mut b_centres = vec![];
         let mut b_qs = vec![];
+        let mut b_gains = vec![];
+        let mut b_thresholds = vec![];
         let mut n_fs = vec![];
+        let mut n_pans = vec![];
 
-        let mut input_subnet = Net32::new(1, size);
+        let mut input_subnet = Net32::new(1 + size, size);
         let mut output_subnet = Net32::new(size, channels);
 
-        let input_pipe = declick_s(0.75);
-
-        let input_pipe_id = match nodes_data.len() {
-            2 => input_subnet.push(Box::new(input_pipe >> split::<U2>())),
-            3 => input_subnet.push(Box::new(input_pipe >> split::<U3>())),
-            4 => input_subnet.push(Box::new(input_pipe >> split::<U4>())),
-            5 => input_subnet.push(Box::new(input_pipe >> split::<U5>())),
-            6 => input_subnet.push(Box::new(input_pipe >> split::<U6>())),
-            9 => input_subnet.push(Box::new(input_pipe >> split::<U9>())),
-            10 => input_subnet.push(Box::new(input_pipe >> split::<U10>())),
-            12 => input_subnet.push(Box::new(input_pipe >> split::<U12>())),
-            n => todo!("support for {n} nodes"),
-        };
+        let input_pipe_id = input_subnet.push(Box::new(declick_s(0.75)));
 
         input_subnet.connect_input(0, input_pipe_id, 0);
 
         for (i, (node_data, tuning)) in nodes_data.iter().zip(tuning).enumerate() {
-            assert_eq!(tuning.0, node_data.f_n, "pair f_n");
-
             let bp_f = shared(tuning.1);
 
             log::debug!(
@@ -67,117 +94,123 @@ impl System {
                 node_data.freq.0
             );
 
-            // todo: use hid input
-            let bp_q = shared(1.0 / size as f32);
-            let ch_mul = 1.0 + MUL - MUL * tuning.2;
+            // resting Q, touch pressure raises it, see `keyboard::pressure_q`
+            let bp_q = shared(resting_q(size));
+            let ch_mul = input_gain(tuning.2);
+            let bp_gain = shared(ch_mul);
+            // open until the node is calibrated
+            let bp_threshold = shared(0.0);
 
             log::info!("amp channel input by {ch_mul}");
             let (n_snp, snp_an) = snoop(SNOOP_SIZE);
             node_snp.push((n_snp, node_data.f_n));
-            let bp_n = mul(ch_mul)
-                >> (pass() | var(&bp_f) | var(&bp_q))
-                >> bandrez()
-                >> pluck(node_data.freq.1, 0.75, 0.25);
-                
+            // shared parameters are smoothed to avoid zipper noise on live updates
+            let band = || {
+                (pass() | (var(&bp_f) >> follow(0.05)) | (var(&bp_q) >> follow(0.05))) >> bandrez()
+            };
+            // the band of the input is heard only while above the room's noise floor
+            let gate = ((pass() >> map(|f: &Frame<f32, U1>| f[0].abs()) >> afollow(0.001, 0.1))
+                | var(&bp_threshold))
+                >> map(|f: &Frame<f32, U2>| if f[0] > f[1] { 1.0_f32 } else { 0.0 })
+                >> follow(0.01);
+            let input_band = (pass() * (var(&bp_gain) >> follow(0.05)))
+                >> band()
+                >> ((pass() ^ gate) >> (pass() * pass()));
+            // the gate on the second input excites the node at its sensitised frequency
+            let gate_band = (pass() * (var(&bp_f) >> follow(0.05) >> sine())) >> band();
+            let bp_n = input_band + gate_band;
+
             b_centres.push(bp_f);
             b_qs.push(bp_q);
+            b_gains.push(bp_gain);
+            b_thresholds.push(bp_threshold);
 
             let bp_id = input_subnet.push(Box::new(bp_n));
+            let exciter_id = input_subnet.push(node_data.voice.exciter(node_data.freq));
 
-            input_subnet.connect(input_pipe_id, i, bp_id, 0);
-            input_subnet.connect_output(bp_id, 0, i);
+            input_subnet.connect(input_pipe_id, 0, bp_id, 0);
+            input_subnet.connect_input(1 + i, bp_id, 1);
+            input_subnet.connect(bp_id, 0, exciter_id, 0);
+            input_subnet.connect_output(exciter_id, 0, i);
 
             let n_f = shared(node_data.freq.0);
-            let mut node = (var(&n_f) | pass()) >> (sine() * follow(0.075)) >> bell_hz(node_data.freq.1, 0.25, 1.75) >> snp_an;
+            let mut tone = node_data.voice.tone(&n_f, node_data.freq);
             n_fs.push(n_f);
+            n_pans.push(
+                pan_gains(node_data.pan, channels)
+                    .into_iter()
+                    .map(shared)
+                    .collect::<Vec<_>>(),
+            );
 
-            log::debug!("created node: {}", node.display());
+            log::debug!("created {:?} node: {}", node_data.voice, tone.display());
 
-            let node_id = output_subnet.push(Box::new(node));
+            let tone_id = output_subnet.push(tone);
+            let node_id = output_subnet.push(Box::new(snp_an));
 
-            output_subnet.connect_input(i, node_id, 0);
+            output_subnet.connect_input(i, tone_id, 0);
+            output_subnet.connect(tone_id, 0, node_id, 0);
 
             nodes.push(node_id);
         }
 
         let (out_snp, an_snp) = snoop(SNOOP_SIZE);
+        let master = shared(1.0);
 
-        let output_pipe_id = match channels {
-            1 => {
-                let (r_f, d_f) = nodes_data
-                    .last()
-                    .map(|n| (n.freq.1 * 5.0, n.freq.1 - n.freq.0))
-                    .unwrap();
-                let r = resonator_hz(r_f, d_f) >> an_snp >> mul(10.0) >> pinkpass();
-
-                match nodes_data.len() {
-                    2 => output_subnet.push(Box::new(join::<U2>() >> r)),
-                    3 => output_subnet.push(Box::new(join::<U3>() >> r)),
-                    5 => output_subnet.push(Box::new(join::<U5>() >> r)),
-                    n => todo!("support {n} nodes, 1 channel"),
-                }
-            }
-            2 => {
-                let (lr_f, ld_f) = nodes_data
-                    .iter()
-                    .filter(|n| n.pan < 0)
-                    .last()
-                    .map(|n| (n.freq.1 * 2.0, n.freq.1 - n.freq.0))
-                    .unwrap();
-                let (rr_f, rd_f) = nodes_data
-                    .iter()
-                    .filter(|n| n.pan > 0)
-                    .last()
-                    .map(|n| (n.freq.1 * 4.0, n.freq.1 - n.freq.0))
-                    .unwrap();
-                let r = (resonator_hz(lr_f, ld_f) | resonator_hz(rr_f, rd_f))
-                    >> (split::<U2>() | split::<U2>())
-                    >> (pass() | join::<U2>() | pass())
-                    >> (pass() | an_snp | pass())
-                    >> (pinkpass() | sink() | pinkpass());
-
-                match nodes_data.len() {
-                    4 => output_subnet.push(Box::new((join::<U2>() | join::<U2>()) >> r)),
-                    6 => output_subnet.push(Box::new((join::<U3>() | join::<U3>()) >> r)),
-                    9 => {
-                        let right = nodes_data.iter().filter(|n| n.pan > 0).count();
-                        let left = nodes_data.iter().filter(|n| n.pan < 0).count();
-                        if right > left {
-                            output_subnet.push(Box::new((join::<U3>() | join::<U6>()) >> r))
-                        } else {
-                            output_subnet.push(Box::new((join::<U6>() | join::<U3>()) >> r))
-                        }
-                    }
-                    10 => output_subnet.push(Box::new((join::<U5>() | join::<U5>()) >> r)),
-                    12 => output_subnet.push(Box::new((join::<U6>() | join::<U6>()) >> r)),
-                    n => todo!("support {n} nodes, 2 channels"),
-                }
+        let output_pipe_id = if channels == 1 {
+            let (r_f, d_f) = nodes_data
+                .last()
+                .map(|n| (n.freq.1 * 5.0, n.freq.1 - n.freq.0))
+                .ok_or_else(|| anyhow!("no nodes for output"))?;
+            let r = resonator_hz(r_f, d_f) >> an_snp >> mul(10.0) >> pinkpass();
+
+            output_subnet.push(Box::new(r))
+        } else {
+            let mut pipe = Net32::new(channels, channels);
+            let snp_mix_id = pipe.push(Box::new(mix(channels)));
+            let snp_id = pipe.push(Box::new(an_snp >> sink()));
+
+            pipe.connect(snp_mix_id, 0, snp_id, 0);
+
+            for ch in 0..channels {
+                let (r_f, d_f) = channel_resonance(nodes_data, ch, channels)
+                    .ok_or_else(|| anyhow!("no nodes for output {ch}"))?;
+                let r_id = pipe.push(Box::new(resonator_hz(r_f, d_f)));
+                let pink_id = pipe.push(Box::new(pinkpass()));
+
+                pipe.connect_input(ch, r_id, 0);
+                pipe.connect(r_id, 0, pink_id, 0);
+                pipe.connect(r_id, 0, snp_mix_id, ch);
+                pipe.connect_output(pink_id, 0, ch);
             }
-            n => todo!("support {n} channels"),
+
+            output_subnet.push(Box::new(pipe))
         };
 
-        match channels {
-            1 => {
-                output_subnet.connect_output(output_pipe_id, 0, 0);
-            }
-            2 => {
-                output_subnet.connect_output(output_pipe_id, 0, 0);
-                output_subnet.connect_output(output_pipe_id, 1, 1);
-            }
-            n => todo!("support {n} channels"),
-        }
+        for ch in 0..channels {
+            output_subnet.connect_output(output_pipe_id, ch, ch);
+
+            let mix_id = output_subnet.push(Box::new(mix(size)));
+            let master_id = output_subnet.push(Box::new(pass() * (var(&master) >> follow(0.05))));
 
-        for (node_id, node_data) in nodes.iter().zip(nodes_data) {
-            if node_data.pan > 0 && channels > 1 {
-                output_subnet.connect(*node_id, 0, output_pipe_id, 1);
-            } else {
-                output_subnet.connect(*node_id, 0, output_pipe_id, 0);
+            for (i, (node_id, gains)) in nodes.iter().zip(n_pans.iter()).enumerate() {
+                let gain_id =
+                    output_subnet.push(Box::new(pass() * (var(&gains[ch]) >> follow(0.05))));
+
+                output_subnet.connect(*node_id, 0, gain_id, 0);
+                output_subnet.connect(gain_id, 0, mix_id, i);
             }
+
+            output_subnet.connect(mix_id, 0, master_id, 0);
+            output_subnet.connect(master_id, 0, output_pipe_id, ch);
         }
 
         log::debug!("created input network: {}", input_subnet.display());
         log::debug!("created output network: {}", output_subnet.display());
 
+        input_subnet.set_sample_rate(sample_rate);
+        output_subnet.set_sample_rate(sample_rate);
+
         let in_id = net.push(Box::new(input_subnet));
         let out_id = net.push(Box::new(output_subnet));
 
@@ -188,9 +221,11 @@ impl System {
         }
 
         for i in 0..size {
+            net.connect_input(1 + i, in_id, 1 + i);
             net.connect(in_id, i, out_id, i);
         }
 
+        net.set_sample_rate(sample_rate);
         net.check();
         log::debug!("created network: {}", net.display());
 
@@ -198,17 +233,879 @@ impl System {
 
         net_be.allocate();
 
-        Self {
+        let fx_be = effects::build(effects, channels, sample_rate);
+
+        Ok(Self {
             channels,
             sample_rate,
             net_be,
+            fx_be,
+            fx_pending: None,
+            mix_data: vec![],
+            gates: vec![0.0; size],
+            gate_data: vec![],
+            gate_changes: Vec::with_capacity(size * 2),
+            in_slices: Vec::with_capacity((1 + size).max(channels)),
+            out_slices: Vec::with_capacity(channels),
             size,
             b_centres,
             b_qs,
+            b_gains,
+            b_thresholds,
+            levels: tuning.iter().map(|t| (t.2, None)).collect(),
             n_fs,
+            n_pans,
             nodes,
+            master,
             out_snp,
-            node_snp
+            out_data: vec![],
+            node_data: node_snp.iter().map(|(_, f_n)| (*f_n, vec![])).collect(),
+            node_snp,
+        })
+    }
+}
+
+/// Fails unless every node has a tuning value for its `f_n`, in the same order
+fn check_pairing(nodes_data: &[Node], tuning: &[TuningValue]) -> Result<()> {
+    if nodes_data.is_empty() {
+        bail!("no nodes");
+    }
+
+    if tuning.len() < nodes_data.len() {
+        bail!("{} nodes but {} tuned", nodes_data.len(), tuning.len());
+    }
+
+    if let Some((node, tuning)) = nodes_data
+        .iter()
+        .zip(tuning)
+        .find(|(node, tuning)| node.f_n != tuning.0)
+    {
+        bail!("node {} tuned as {}", node.f_n, tuning.0);
+    }
+
+    Ok(())
+}
+
+impl System {
+    /// Runs the nodes and then the effects chain on one block,
+    /// allocating only when the block grows
+    pub fn process<I, O>(&mut self, size: usize, input: &[I], output: &mut [O])
+    where
+        I: AsRef<[f32]>,
+        O: AsMut<[f32]>,
+    {
+        self.swap_effects();
+
+        if self.mix_data.len() != self.channels || self.mix_data[0].len() < size {
+            self.mix_data = vec![vec![0_f32; size]; self.channels];
+        }
+
+        if self.gate_data.len() != self.size || self.gate_data.iter().any(|g| g.len() < size) {
+            self.gate_data = vec![vec![0_f32; size]; self.size];
+        }
+
+        self.gate_changes.sort_by_key(|(offset, _, _)| *offset);
+
+        for (i, (gate, data)) in self
+            .gates
+            .iter_mut()
+            .zip(self.gate_data.iter_mut())
+            .enumerate()
+        {
+            let mut from = 0;
+            for (offset, _, level) in self.gate_changes.iter().filter(|(_, n, _)| *n == i) {
+                let offset = (*offset).min(size);
+                data[from..offset].fill(*gate);
+                *gate = *level;
+                from = offset;
+            }
+            data[from..size].fill(*gate);
+        }
+
+        self.gate_changes.clear();
+
+        let mut in_slices: Vec<&[f32]> = recycle(mem::take(&mut self.in_slices));
+        in_slices.extend(
+            input
+                .iter()
+                .take(1)
+                .map(|ch| &ch.as_ref()[..size])
+                .chain(self.gate_data.iter().map(|g| &g[..size])),
+        );
+
+        let mut out_slices: Vec<&mut [f32]> = recycle(mem::take(&mut self.out_slices));
+        out_slices.extend(self.mix_data.iter_mut().map(|ch| &mut ch[..size]));
+
+        self.net_be
+            .process(size, in_slices.as_slice(), out_slices.as_mut_slice());
+
+        // the mix is read once its mutable borrows are let go
+        let mut out_slices: Vec<&mut [f32]> = recycle(out_slices);
+        let mut in_slices: Vec<&[f32]> = recycle(in_slices);
+        in_slices.extend(self.mix_data.iter().map(|ch| &ch[..size]));
+        out_slices.extend(output.iter_mut().map(|ch| &mut ch.as_mut()[..size]));
+
+        self.fx_be
+            .process(size, in_slices.as_slice(), out_slices.as_mut_slice());
+
+        self.in_slices = recycle(in_slices);
+        self.out_slices = recycle(out_slices);
+    }
+
+    /// Replaces the effects chain, leaving the nodes running.
+    /// The chain is built on its own thread and swapped in at the start of a block once ready
+    pub fn set_effects(&mut self, chain: &FxChain) {
+        let (sender, receiver) = channel();
+        let (chain, channels, sample_rate) = (chain.clone(), self.channels, self.sample_rate);
+
+        let spawned = thread::Builder::new()
+            .name("aucore effects".to_string())
+            .spawn(move || {
+                _ = sender.send(effects::build(&chain, channels, sample_rate));
+            });
+
+        match spawned {
+            Ok(_) => _ = self.fx_pending.insert(receiver),
+            Err(e) => log::error!("build effects: {e:?}"),
+        }
+    }
+
+    fn swap_effects(&mut self) {
+        let Some(pending) = self.fx_pending.as_ref() else {
+            return;
+        };
+
+        match pending.try_recv() {
+            Ok(fx_be) => {
+                self.fx_be = fx_be;
+                self.fx_pending = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                log::error!("effects chain wasn't built");
+                self.fx_pending = None;
+            }
+        }
+    }
+
+    /// Sets the excitation gate of node `f_n` to `level` at `offset` frames into the next block,
+    /// driving the node without any input
+    pub fn set_node_gate(&mut self, f_n: usize, offset: usize, level: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| self.gate_changes.push((offset, i, level)))
+            .is_some()
+    }
+
+    /// Closes every gate at the start of the next block
+    pub fn release_gates(&mut self) {
+        self.gate_changes
+            .extend((0..self.size).map(|i| (0, i, 0.0)));
+    }
+
+    pub fn set_master_gain(&self, gain: f32) {
+        self.master.set_value(gain);
+    }
+
+    /// Takes in new snoop buffers, keeping the latest of each
+    pub fn read_snoops(&mut self) {
+        if let Some(snp) = self.out_snp.get() {
+            self.out_data = (0..snp.size()).map(|i| snp.at(i)).collect();
+        }
+
+        for ((snp, _), (_, data)) in self.node_snp.iter_mut().zip(self.node_data.iter_mut()) {
+            if let Some(snp) = snp.get() {
+                *data = (0..snp.size()).map(|i| snp.at(i)).collect();
+            }
+        }
+    }
+
+    /// RMS of the latest snoop data of each node
+    pub fn node_levels(&self) -> Vec<(usize, f32)> {
+        self.node_data
+            .iter()
+            .map(|(f_n, data)| {
+                let power = data.iter().map(|s| s * s).sum::<f32>() / data.len().max(1) as f32;
+                (*f_n, power.sqrt())
+            })
+            .collect()
+    }
+
+    fn node_index(&self, f_n: usize) -> Option<usize> {
+        self.node_snp.iter().position(|(_, n)| *n == f_n)
+    }
+
+    pub fn set_node_sensitivity(&self, f_n: usize, freq: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| self.b_centres[i].set_value(freq))
+            .is_some()
+    }
+
+    pub fn set_node_q(&self, f_n: usize, q: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| self.b_qs[i].set_value(q))
+            .is_some()
+    }
+
+    pub fn set_node_gain(&mut self, f_n: usize, amp: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| {
+                self.levels[i].0 = amp;
+                self.apply_levels(i);
+            })
+            .is_some()
+    }
+
+    /// Derives each listed node's gain and gate threshold from its measured noise floor
+    pub fn set_noise_floor(&mut self, floor: &[(usize, f32)]) {
+        for (f_n, level) in floor {
+            if let Some(i) = self.node_index(*f_n) {
+                self.levels[i].1 = Some(*level);
+                self.apply_levels(i);
+            }
+        }
+    }
+
+    fn apply_levels(&self, i: usize) {
+        let (amp, floor) = self.levels[i];
+        // uncalibrated nodes keep the plain amplitude curve
+        let gain = floor.map_or_else(|| input_gain(amp), |floor| node_gain(amp, floor));
+
+        self.b_gains[i].set_value(gain);
+        self.b_thresholds[i].set_value(floor.map_or(0.0, |floor| gate_threshold(floor, gain)));
+    }
+
+    pub fn set_node_freq(&self, f_n: usize, freq: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| self.n_fs[i].set_value(freq))
+            .is_some()
+    }
+
+    pub fn set_node_pan(&self, f_n: usize, pan: f32) -> bool {
+        self.node_index(f_n)
+            .map(|i| {
+                for (gain, value) in self.n_pans[i].iter().zip(pan_gains(pan, self.channels)) {
+                    gain.set_value(value);
+                }
+            })
+            .is_some()
+    }
+}
+
+/// Empties a vec of borrows to hold borrows of another lifetime,
+/// collecting in place so that its allocation is kept
+fn recycle<S, U>(mut slices: Vec<S>) -> Vec<U> {
+    slices.clear();
+    slices.into_iter().map(|_| unreachable!()).collect()
+}
+
+/// maps tuning amplitude to the node input gain
+pub fn input_gain(amp: f32) -> f32 {
+    1.0 + MUL - MUL * amp
+}
+
+/// band-pass Q of every node at rest, narrower the more nodes share the input
+pub fn resting_q(size: usize) -> f32 {
+    1.0 / size.max(1) as f32
+}
+
+/// Equal-power gains between the two output channels adjacent to `pan`,
+/// with the channels evenly spread from -1.0 to 1.0
+pub fn pan_gains(pan: f32, channels: usize) -> Vec<f32> {
+    if channels < 2 {
+        return vec![1.0; channels];
+    }
+
+    let pos = (pan.clamp(-1.0, 1.0) + 1.0) / 2.0 * (channels - 1) as f32;
+    let left = (pos.floor() as usize).min(channels - 2);
+    let frac = (pos - left as f32) * std::f32::consts::FRAC_PI_2;
+
+    let mut gains = vec![0.0; channels];
+    gains[left] = frac.cos();
+    gains[left + 1] = frac.sin();
+
+    gains
+}
+
+fn nearest_channel(pan: f32, channels: usize) -> usize {
+    ((pan.clamp(-1.0, 1.0) + 1.0) / 2.0 * (channels - 1) as f32).round() as usize
+}
+
+/// resonator frequency and bandwidth of an output channel,
+/// after the last node panned nearest to it
+fn channel_resonance(nodes_data: &[Node], ch: usize, channels: usize) -> Option<(f32, f32)> {
+    let mul = 2.0 + 2.0 * ch as f32 / (channels - 1) as f32;
+
+    nodes_data
+        .iter()
+        .filter(|n| nearest_channel(n.pan, channels) == ch)
+        .last()
+        .or(nodes_data.last())
+        .map(|n| (n.freq.1 * mul, n.freq.1 - n.freq.0))
+}
+
+/// sums any number of inputs into one output
+fn mix(size: usize) -> Net32 {
+    let mut net = Net32::new(size, 1);
+
+    if size == 0 {
+        let silence_id = net.push(Box::new(dc(0.0)));
+        net.connect_output(silence_id, 0, 0);
+        return net;
+    }
+
+    let mut acc_id = net.push(Box::new(pass()));
+    net.connect_input(0, acc_id, 0);
+
+    for i in 1..size {
+        let sum_id = net.push(Box::new(pass() + pass()));
+        net.connect(acc_id, 0, sum_id, 0);
+        net.connect_input(i, sum_id, 1);
+        acc_id = sum_id;
+    }
+
+    net.connect_output(acc_id, 0, 0);
+
+    net
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_core::{
+        instrument::{keyboard, node::spawn_all_nodes, Config, Voice},
+        play::FxUnit,
+    };
+    use hecs::World;
+
+    const BLOCK: usize = 128;
+
+    fn nodes_for(config: &Config) -> Vec<Node> {
+        (0..config.n_buttons)
+            .map(|idx| {
+                let group = idx / config.buttons_group + 1;
+                let f_n = config.n_buttons - idx;
+                let freq = config.f0 * (f_n * 2) as f32 - config.f0;
+                Node {
+                    freq: (freq, freq + config.f0),
+                    f_n,
+                    pan: if group % 2 == 0 { -1.0 } else { 1.0 },
+                    triggered: 0.0,
+                    voice: Voice::default(),
+                }
+            })
+            .collect()
+    }
+
+    fn tuning_for(nodes: &[Node]) -> Vec<TuningValue> {
+        nodes.iter().map(|n| (n.f_n, n.freq.0, 0.5)).collect()
+    }
+
+    fn process_block(sys: &mut System) -> Vec<Vec<f32>> {
+        let input = (0..BLOCK)
+            .map(|i| (i as f32 * 0.1).sin())
+            .collect::<Vec<_>>();
+        let mut output = vec![vec![0_f32; BLOCK]; sys.channels];
+        let mut output_slices = output
+            .iter_mut()
+            .map(|ch| ch.as_mut_slice())
+            .collect::<Vec<_>>();
+
+        sys.process(BLOCK, &[input.as_slice()], output_slices.as_mut_slice());
+
+        output
+    }
+
+    #[test]
+    fn builds_for_every_groups_and_buttons_combination() {
+        for buttons_group in [2, 3, 5] {
+            for groups in 1..=12 {
+                let config = Config {
+                    groups,
+                    buttons_group,
+                    n_buttons: groups * buttons_group,
+                    f0: 110.0,
+                    ..Default::default()
+                };
+                let nodes = nodes_for(&config);
+                let tuning = tuning_for(&nodes);
+
+                let channels = groups.min(6);
+
+                let mut sys = System::new(
+                    &nodes,
+                    &tuning,
+                    channels,
+                    DEFAULT_SAMPLE_RATE,
+                    &FxChain::default(),
+                )
+                .expect("system");
+
+                assert_eq!(sys.channels, channels);
+                assert_eq!(sys.nodes.len(), config.n_buttons);
+
+                let output = process_block(&mut sys);
+                assert_eq!(output.len(), sys.channels);
+                assert!(output.iter().flatten().all(|s| s.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn builds_for_a_single_node_and_fails_for_none() {
+        for channels in [1, 2, 4] {
+            let config = Config {
+                groups: 1,
+                buttons_group: 1,
+                n_buttons: 1,
+                f0: 110.0,
+                ..Default::default()
+            };
+            let nodes = nodes_for(&config);
+            let tuning = tuning_for(&nodes);
+
+            let mut sys = System::new(
+                &nodes,
+                &tuning,
+                channels,
+                DEFAULT_SAMPLE_RATE,
+                &FxChain::default(),
+            )
+            .expect("system");
+
+            let output = process_block(&mut sys);
+            assert_eq!(output.len(), channels);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+
+            assert!(
+                System::new(&[], &[], channels, DEFAULT_SAMPLE_RATE, &FxChain::default()).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn fails_for_mismatched_tuning() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 2,
+            n_buttons: 4,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let build = |tuning: &[TuningValue]| {
+            System::new(
+                &nodes,
+                tuning,
+                DEFAULT_CHANNELS,
+                DEFAULT_SAMPLE_RATE,
+                &FxChain::default(),
+            )
+        };
+
+        let tuning = tuning_for(&nodes);
+        assert!(build(&tuning[1..]).is_err());
+
+        let mut reversed = tuning.clone();
+        reversed.reverse();
+        assert!(build(&reversed).is_err());
+    }
+
+    #[test]
+    fn updates_node_params_in_place() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 2,
+            n_buttons: 4,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let tuning = tuning_for(&nodes);
+        let mut sys = System::new(
+            &nodes,
+            &tuning,
+            DEFAULT_CHANNELS,
+            DEFAULT_SAMPLE_RATE,
+            &FxChain::default(),
+        )
+        .expect("system");
+
+        assert!(sys.set_node_sensitivity(2, 440.0));
+        assert!(sys.set_node_q(2, 0.5));
+        assert!(sys.set_node_gain(2, 0.25));
+        assert!(sys.set_node_freq(2, 550.0));
+        assert!(sys.set_node_pan(2, 0.0));
+        assert!(!sys.set_node_freq(42, 550.0));
+
+        let i = sys.node_index(2).unwrap();
+        assert_eq!(sys.b_centres[i].value(), 440.0);
+        assert_eq!(sys.b_qs[i].value(), 0.5);
+        assert_eq!(sys.b_gains[i].value(), input_gain(0.25));
+        assert_eq!(sys.b_thresholds[i].value(), 0.0);
+
+        sys.set_noise_floor(&[(2, 1e-3), (42, 1e-3)]);
+        assert_eq!(sys.b_gains[i].value(), node_gain(0.25, 1e-3));
+        assert_eq!(
+            sys.b_thresholds[i].value(),
+            gate_threshold(1e-3, node_gain(0.25, 1e-3))
+        );
+        assert_eq!(sys.n_fs[i].value(), 550.0);
+        assert_eq!(
+            sys.n_pans[i].iter().map(|g| g.value()).collect::<Vec<_>>(),
+            pan_gains(0.0, sys.channels)
+        );
+
+        let output = process_block(&mut sys);
+        assert!(output.iter().flatten().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn builds_for_config_layouts() {
+        for (width, height, dpi) in [
+            (1920.0, 1080.0, 96.0),
+            (2732.0, 2048.0, 264.0),
+            (2436.0, 1125.0, 458.0),
+            (1080.0, 2340.0, 394.0),
+            (1280.0, 800.0, 149.0),
+            (2048.0, 1536.0, 264.0),
+            (430.0, 932.0, 476.0),
+        ] {
+            let config = Config::new(width, height, dpi, [50.0, 20.0, 10.0, 25.0]);
+            let mut world = World::new();
+            keyboard::Keyboard::spawn(&mut world, &config);
+            let nodes = spawn_all_nodes(&mut world, &config)
+                .into_iter()
+                .map(|e| *world.get::<&Node>(e).expect("node for entity"))
+                .collect::<Vec<_>>();
+            let tuning = tuning_for(&nodes);
+
+            let mut sys = System::new(
+                &nodes,
+                &tuning,
+                DEFAULT_CHANNELS,
+                DEFAULT_SAMPLE_RATE,
+                &FxChain::default(),
+            )
+            .expect("system");
+
+            assert_eq!(sys.nodes.len(), config.n_buttons);
+
+            let output = process_block(&mut sys);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+        }
+    }
+
+    #[test]
+    fn builds_every_voice() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 5,
+            n_buttons: 10,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let voices = [
+            Voice::SineBell,
+            Voice::FmBell,
+            Voice::NoiseChime,
+            Voice::String,
+            Voice::Organ,
+        ];
+        let nodes = nodes_for(&config)
+            .into_iter()
+            .zip(voices.iter().cycle())
+            .map(|(node, voice)| Node {
+                voice: *voice,
+                ..node
+            })
+            .collect::<Vec<_>>();
+        let tuning = tuning_for(&nodes);
+
+        let mut sys = System::new(
+            &nodes,
+            &tuning,
+            DEFAULT_CHANNELS,
+            DEFAULT_SAMPLE_RATE,
+            &FxChain::default(),
+        )
+        .expect("system");
+
+        for _ in 0..8 {
+            let output = process_block(&mut sys);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+        }
+    }
+
+    /// Magnitude of `signal` at `freq`, from a single DFT bin
+    fn magnitude_at(signal: &[f32], freq: f64, sample_rate: f64) -> f64 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, s)| {
+                let phase = std::f64::consts::TAU * freq * i as f64 / sample_rate;
+                (re + *s as f64 * phase.cos(), im - *s as f64 * phase.sin())
+            });
+
+        (re * re + im * im).sqrt() / signal.len() as f64
+    }
+
+    #[test]
+    fn fm_bell_has_sidebands() {
+        use crate::voice::NodeVoice;
+
+        let freq = 220.0;
+        let sample_rate = DEFAULT_SAMPLE_RATE;
+        // carrier against the first upper sideband, at the carrier plus the modulator
+        let ratio = |voice: Voice| {
+            let mut tone = voice.tone(&shared(freq), (freq, freq));
+            tone.set_sample_rate(sample_rate);
+
+            let mut out = [0_f32];
+            let signal = (0..sample_rate as usize * 3 / 2)
+                .map(|_| {
+                    tone.tick(&[1.0], &mut out);
+                    out[0]
+                })
+                .skip(sample_rate as usize / 2)
+                .collect::<Vec<_>>();
+            assert!(signal.iter().all(|s| s.is_finite()));
+
+            let carrier = magnitude_at(&signal, freq as f64, sample_rate);
+            let sideband = magnitude_at(&signal, freq as f64 * 2.4, sample_rate);
+            sideband / carrier
+        };
+
+        assert!(ratio(Voice::FmBell) > 0.1);
+        assert!(ratio(Voice::SineBell) < 0.01);
+    }
+
+    #[test]
+    fn builds_for_device_sample_rates() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 3,
+            n_buttons: 6,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let tuning = tuning_for(&nodes);
+
+        for sample_rate in [22050.0, 44100.0, 48000.0, 96000.0] {
+            let mut sys = System::new(
+                &nodes,
+                &tuning,
+                DEFAULT_CHANNELS,
+                sample_rate,
+                &FxChain::default(),
+            )
+            .expect("system");
+            assert_eq!(sys.sample_rate, sample_rate);
+
+            let output = process_block(&mut sys);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
         }
     }
+
+    #[test]
+    fn reuses_its_buffers_between_blocks() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 2,
+            n_buttons: 4,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let tuning = tuning_for(&nodes);
+        let mut sys = System::new(
+            &nodes,
+            &tuning,
+            DEFAULT_CHANNELS,
+            DEFAULT_SAMPLE_RATE,
+            &FxChain::default(),
+        )
+        .expect("system");
+
+        process_block(&mut sys);
+        let buffers = |sys: &System| {
+            (
+                sys.in_slices.as_ptr() as usize,
+                sys.out_slices.as_ptr() as usize,
+                sys.mix_data[0].as_ptr() as usize,
+                sys.gate_data[0].as_ptr() as usize,
+            )
+        };
+        let first = buffers(&sys);
+
+        for _ in 0..4 {
+            process_block(&mut sys);
+            assert_eq!(buffers(&sys), first);
+        }
+        assert!(sys.in_slices.is_empty() && sys.out_slices.is_empty());
+    }
+
+    #[test]
+    fn swaps_effects_at_runtime() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 2,
+            n_buttons: 4,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let tuning = tuning_for(&nodes);
+        let mut sys = System::new(
+            &nodes,
+            &tuning,
+            DEFAULT_CHANNELS,
+            DEFAULT_SAMPLE_RATE,
+            &FxChain::default(),
+        )
+        .expect("system");
+
+        process_block(&mut sys);
+
+        sys.set_effects(&FxChain(vec![
+            FxUnit::Reverb {
+                room_size: 20.0,
+                time: 2.0,
+                mix: 0.3,
+            },
+            FxUnit::Limiter {
+                attack: 0.005,
+                release: 0.2,
+            },
+        ]));
+
+        let started = std::time::Instant::now();
+        while sys.fx_pending.is_some() {
+            assert!(started.elapsed().as_secs() < 10, "effects never swapped in");
+
+            let output = process_block(&mut sys);
+            assert_eq!(output.len(), sys.channels);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+        }
+
+        for _ in 0..4 {
+            let output = process_block(&mut sys);
+            assert_eq!(output.len(), sys.channels);
+            assert!(output.iter().flatten().all(|s| s.is_finite()));
+        }
+    }
+
+    #[test]
+    fn pans_with_equal_power() {
+        for channels in [2, 4, 6] {
+            for pan in [-1.0, -0.6, -0.1, 0.0, 0.35, 0.8, 1.0] {
+                let gains = pan_gains(pan, channels);
+                let power = gains.iter().map(|g| g * g).sum::<f32>();
+
+                assert_eq!(gains.len(), channels);
+                assert!(
+                    (power - 1.0).abs() < 1e-5,
+                    "{pan} over {channels}: {gains:?}"
+                );
+                assert!(gains.iter().filter(|g| **g > 1e-5).count() <= 2);
+            }
+
+            assert_eq!(pan_gains(-1.0, channels)[0], 1.0);
+            assert!((pan_gains(1.0, channels)[channels - 1] - 1.0).abs() < 1e-5);
+        }
+
+        assert_eq!(pan_gains(0.5, 1), vec![1.0]);
+    }
+
+    #[test]
+    fn renders_multichannel_rigs() {
+        let config = Config {
+            groups: 3,
+            buttons_group: 3,
+            n_buttons: 9,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config)
+            .into_iter()
+            .enumerate()
+            .map(|(i, node)| Node {
+                pan: i as f32 / 4.0 - 1.0,
+                ..node
+            })
+            .collect::<Vec<_>>();
+        let tuning = tuning_for(&nodes);
+
+        for channels in [4, 6, 8] {
+            let mut sys = System::new(
+                &nodes,
+                &tuning,
+                channels,
+                DEFAULT_SAMPLE_RATE,
+                &FxChain::default(),
+            )
+            .expect("system");
+
+            assert!(sys.set_node_pan(nodes[0].f_n, 0.5));
+
+            for _ in 0..4 {
+                let output = process_block(&mut sys);
+                assert_eq!(output.len(), channels);
+                assert!(output.iter().flatten().all(|s| s.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn gates_drive_nodes_without_input() {
+        let config = Config {
+            groups: 2,
+            buttons_group: 2,
+            n_buttons: 4,
+            f0: 110.0,
+            ..Default::default()
+        };
+        let nodes = nodes_for(&config);
+        let tuning = tuning_for(&nodes);
+
+        let energy = |gate: Option<usize>| {
+            let mut sys = System::new(
+                &nodes,
+                &tuning,
+                DEFAULT_CHANNELS,
+                DEFAULT_SAMPLE_RATE,
+                &FxChain::default(),
+            )
+            .expect("system");
+            if let Some(f_n) = gate {
+                assert!(sys.set_node_gate(f_n, BLOCK / 2, 1.0));
+            }
+
+            let silence = vec![0_f32; BLOCK];
+            let mut output = vec![vec![0_f32; BLOCK]; sys.channels];
+            let mut energy = 0.0;
+            for _ in 0..32 {
+                let mut output_slices = output
+                    .iter_mut()
+                    .map(|ch| ch.as_mut_slice())
+                    .collect::<Vec<_>>();
+                sys.process(BLOCK, &[silence.as_slice()], output_slices.as_mut_slice());
+                energy += output.iter().flatten().map(|s| s * s).sum::<f32>();
+            }
+
+            sys.release_gates();
+            energy
+        };
+
+        assert!(energy(Some(2)) > energy(None));
+        assert!(!System::new(
+            &nodes,
+            &tuning,
+            DEFAULT_CHANNELS,
+            DEFAULT_SAMPLE_RATE,
+            &FxChain::default()
+        )
+        .expect("system")
+        .set_node_gate(42, 0, 1.0));
+    }
 }
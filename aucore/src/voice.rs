@@ -0,0 +1,61 @@
+use app_core::instrument::Voice;
+use fundsp::hacker32::*;
+
+/// Sound of a node: an exciter driven by the sensitised input
+/// and a tone driven by the exciter
+pub trait NodeVoice {
+    /// one input (band-passed microphone), one output
+    fn exciter(&self, freq: (f32, f32)) -> Box<dyn AudioUnit32>;
+
+    /// one input (exciter), one output; `n_f` is the live output frequency
+    fn tone(&self, n_f: &Shared<f32>, freq: (f32, f32)) -> Box<dyn AudioUnit32>;
+}
+
+impl NodeVoice for Voice {
+    fn exciter(&self, freq: (f32, f32)) -> Box<dyn AudioUnit32> {
+        match self {
+            // the string is its own exciter
+            Voice::String => Box::new(pass()),
+            Voice::SineBell | Voice::FmBell | Voice::NoiseChime | Voice::Organ => {
+                Box::new(pluck(freq.1, 0.75, 0.25))
+            }
+        }
+    }
+
+    fn tone(&self, n_f: &Shared<f32>, freq: (f32, f32)) -> Box<dyn AudioUnit32> {
+        match self {
+            Voice::SineBell => Box::new(
+                ((var(n_f) >> follow(0.05)) | pass())
+                    >> (sine() * follow(0.075))
+                    >> bell_hz(freq.1, 0.25, 1.75),
+            ),
+            Voice::FmBell => {
+                // the modulator stays audible: only the carrier's frequency is smoothed
+                let carrier = || var(n_f) >> follow(0.05);
+                let modulator = ((carrier() * 1.4) >> sine()) * (carrier() * 2.0);
+                Box::new(
+                    ((carrier() + modulator) | pass())
+                        >> (sine() * follow(0.1))
+                        >> bell_hz(freq.1, 0.5, 1.5),
+                )
+            }
+            Voice::NoiseChime => Box::new(
+                (((noise() | (var(n_f) >> follow(0.05)) | dc(50.0)) >> bandpass())
+                    * follow(0.2))
+                    >> mul(4.0)
+                    >> bell_hz(freq.1, 0.25, 1.25),
+            ),
+            // Karplus-Strong string, the loop delay following the live frequency
+            Voice::String => Box::new(feedback(
+                (pass() | (var_fn(n_f, |f| 1.0 / f.max(20.0)) >> follow(0.05)))
+                    >> tap(1.0 / 20000.0, 1.0 / 20.0)
+                    >> lowpass_hz(freq.1 * 4.0, 0.7) * 0.995,
+            )),
+            Voice::Organ => Box::new(
+                ((var(n_f) >> follow(0.05)) | pass())
+                    >> (organ() * follow(0.2))
+                    >> lowpass_hz(freq.1 * 3.0, 0.5),
+            ),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "osc")]
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use app_core::play::PlayOperation;
+use aucore::osc::OscEndpoint;
+use rosc::{decoder, encoder, OscMessage, OscPacket, OscType};
+
+fn client() -> UdpSocket {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).expect("bind client");
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .expect("client timeout");
+    socket
+}
+
+fn send(socket: &UdpSocket, endpoint: &OscEndpoint, addr: &str, value: f32) {
+    let packet = OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args: vec![OscType::Float(value)],
+    });
+    let bytes = encoder::encode(&packet).expect("encode");
+    socket
+        .send_to(bytes.as_slice(), endpoint.local_addr())
+        .expect("send");
+}
+
+fn poll_until(endpoint: &OscEndpoint, count: usize) -> Vec<PlayOperation> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut ops = vec![];
+
+    while ops.len() < count && Instant::now() < deadline {
+        ops.extend(std::iter::from_fn(|| endpoint.next_op()));
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    ops
+}
+
+#[test]
+fn receives_node_and_master_controls() {
+    let endpoint = OscEndpoint::bind(0).expect("bind endpoint");
+    let socket = client();
+
+    send(&socket, &endpoint, "/node/2/freq", 440.0);
+    send(&socket, &endpoint, "/node/2/q", 0.25);
+    send(&socket, &endpoint, "/master/gain", 0.5);
+
+    assert_eq!(
+        poll_until(&endpoint, 3),
+        vec![
+            PlayOperation::NodeFreq(2, 440.0),
+            PlayOperation::NodeQ(2, 0.25),
+            PlayOperation::MasterGain(0.5),
+        ]
+    );
+}
+
+#[test]
+fn broadcasts_levels_to_peers() {
+    let endpoint = OscEndpoint::bind(0).expect("bind endpoint");
+    let socket = client();
+
+    send(&socket, &endpoint, "/master/gain", 1.0);
+    poll_until(&endpoint, 1);
+
+    endpoint.broadcast(vec![(1, 0.5), (2, 0.25)], &[0.0, 0.1, -0.1]);
+
+    let mut buf = [0_u8; decoder::MTU];
+    let (len, _) = socket.recv_from(&mut buf).expect("receive broadcast");
+    let (_, packet) = decoder::decode_udp(&buf[..len]).expect("decode broadcast");
+
+    let OscPacket::Bundle(bundle) = packet else {
+        panic!("expected a bundle");
+    };
+    let messages = bundle
+        .content
+        .into_iter()
+        .filter_map(|p| match p {
+            OscPacket::Message(msg) => Some(msg),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].addr, "/node/1/level");
+    assert_eq!(messages[0].args, vec![OscType::Float(0.5)]);
+    assert_eq!(messages[1].addr, "/node/2/level");
+    assert_eq!(messages[2].addr, "/output/snoop");
+    assert_eq!(messages[2].args.len(), 3);
+}
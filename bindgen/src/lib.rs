@@ -83,4 +83,29 @@ pub async fn au_receive(arc_self: Arc<AUReceiver>) -> Option<Vec<u8>> {
     arc_self.au_receive().await
 }
 
+#[derive(uniffi::Object)]
+pub struct AUCaptureReceiver(Mutex<aucore::CaptureReceiver>);
+
+impl AUCaptureReceiver {
+    pub fn au_capture(au: &AUCoreBridge) -> AUCaptureReceiver {
+        AUCaptureReceiver(Mutex::new(au.0.capture()))
+    }
+
+    async fn au_receive_capture(&self) -> Option<Vec<u8>> {
+        let mut rx = self.0.lock().await;
+        rx.next().await
+    }
+}
+
+/// Subscribes to serialized `CaptureOutput`, replacing any earlier subscription
+#[uniffi::export]
+pub fn au_capture(arc_self: Arc<AUCoreBridge>) -> Arc<AUCaptureReceiver> {
+    Arc::new(AUCaptureReceiver::au_capture(arc_self.as_ref()))
+}
+
+#[uniffi::export]
+pub async fn au_receive_capture(arc_self: Arc<AUCaptureReceiver>) -> Option<Vec<u8>> {
+    arc_self.au_receive_capture().await
+}
+
 uniffi::include_scaffolding!("ffirs");
@@ -23,6 +23,12 @@ class Core: ObservableObject {
     init() {
         self.view = try! .bincodeDeserialize(input: [UInt8](RedSiren.view()))
         logInit()
+        playback.onCapture = { data in
+            DispatchQueue.main.async {
+                let capture: CaptureOutput = try! .bincodeDeserialize(input: [UInt8](data))
+                self.update(.capture(capture))
+            }
+        }
     }
 
     func update(_ event: Event) {
@@ -102,10 +108,28 @@ class Core: ObservableObject {
         case .animate(.stop):
             self.stopClock!()
             break
+        case .midi(.export(let smf)):
+            saveFile("red-siren.mid", smf)
+        case .midi:
+            break
+        case .share(.recording(let wav)):
+            saveFile("red-siren.wav", wav)
         }
 
         
     }
+    
+    func saveFile(_ name: String, _ bytes: [UInt8]) {
+        let url = FileManager.default.urls(for: .documentDirectory, in: .userDomainMask)[0]
+            .appendingPathComponent(name)
+        do {
+            try Data(bytes).write(to: url)
+            Logger().log("saved \(url)")
+        }
+        catch {
+            Logger().error("\(name) not saved: \(error)")
+        }
+    }
 }
 
 protocol CoreEnv {
@@ -16,6 +16,18 @@ class Playback: NSObject, ObservableObject {
     private var session: AVAudioSession?
     private var auCore: AuCoreBridge?
 
+    public var onCapture: ((_ data: Data) -> Void)?
+
+    // one bridge for the app's lifetime, reinstalling keeps the selected devices
+    private var bridge: AuCoreBridge {
+        if let auCore = auCore {
+            return auCore
+        }
+        let auCore = auNew()
+        self.auCore = auCore
+        return auCore
+    }
+
     override init() {
 
     }
@@ -25,23 +37,31 @@ class Playback: NSObject, ObservableObject {
         case .permissions:
             Task {
                 let grant = await isAuthorized
-                let data = try! [UInt8](PlayOperationOutput.permission(grant).bincodeSerialize())
+                let output = grant ? PlayOperationOutput.success : PlayOperationOutput.failure(.permissionDenied)
+                let data = try! [UInt8](output.bincodeSerialize())
                 onData(Data(data))
                 
                 Logger().log("playback permissions task complete")
             }
         case .installAU:
             guard setupAudioSession() else {
-                let data = try! PlayOperationOutput.success(false).bincodeSerialize()
+                let data = try! PlayOperationOutput.failure(.deviceUnavailable("audio session setup failed")).bincodeSerialize()
 
                 onData(Data(data))
                 
                 return
             }
-            auCore = auNew()
+            let capture = auCapture(self.bridge)
+            Task {
+                while let data = await auReceiveCapture(capture) {
+                    self.onCapture?(data)
+                }
+
+                Logger().log("playback capture task complete")
+            }
             do {
                 let opData = try op.bincodeSerialize()
-                let rcv = auRequest(self.auCore!, Data.init(opData))
+                let rcv = auRequest(self.bridge, Data.init(opData))
 
                 Task {
                     if let data = await auReceive(rcv) {
@@ -52,13 +72,13 @@ class Playback: NSObject, ObservableObject {
                 }
             }
             catch {
-                let data = try! [UInt8](PlayOperationOutput.success(false).bincodeSerialize())
+                let data = try! [UInt8](PlayOperationOutput.failure(.protocolMismatch(error.localizedDescription)).bincodeSerialize())
                 onData(Data(data))
             }
         default:
             do {
                 let opData = try op.bincodeSerialize()
-                let rcv = auRequest(self.auCore!, Data.init(opData))
+                let rcv = auRequest(self.bridge, Data.init(opData))
 
                 Task {
                     while let data = await auReceive(rcv) {
@@ -69,7 +89,7 @@ class Playback: NSObject, ObservableObject {
                 }
             }
             catch {
-                let data = try! [UInt8](PlayOperationOutput.success(false).bincodeSerialize())
+                let data = try! [UInt8](PlayOperationOutput.failure(.protocolMismatch(error.localizedDescription)).bincodeSerialize())
                 onData(Data(data))
             }
 
@@ -7,12 +7,27 @@ fn main() -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed=../aucore");
 
     {
-        use app_core::instrument::{Config, Node};
+        use app_core::{
+            instrument::{Config, Node, Voice},
+            play::{
+                AnalysisConfig, AudioDevice, Binning, DeviceSelection, FxChain, FxUnit, PlayError,
+                Window,
+            },
+        };
         use aucore::RedSirenAU;
 
         let mut gen = TypeGen::new();
         gen.register_type::<Config>()?;
+        gen.register_type::<Voice>()?;
         gen.register_type::<Node>()?;
+        gen.register_type::<FxUnit>()?;
+        gen.register_type::<FxChain>()?;
+        gen.register_type::<Window>()?;
+        gen.register_type::<Binning>()?;
+        gen.register_type::<AnalysisConfig>()?;
+        gen.register_type::<PlayError>()?;
+        gen.register_type::<AudioDevice>()?;
+        gen.register_type::<DeviceSelection>()?;
         gen.register_app::<RedSirenAU>()?;
 
         let output_root = PathBuf::from("./generated");
@@ -30,19 +45,30 @@ fn main() -> anyhow::Result<()> {
     {
         use app_core::{
             geometry::{Line, Rect},
-            instrument::{layout::MenuPosition, Config, InstrumentEV, Layout, Node, PlaybackEV},
+            instrument::{
+                layout::MenuPosition, Config, EnvelopeParams, InstrumentEV, Layout, Node,
+                NodeEvent, PlaybackEV, Voice,
+            },
             intro::IntroEV,
-            play::CaptureOutput,
-            tuner::{TriggerState, TunerEV},
+            midi::MidiMessage,
+            play::{
+                AnalysisConfig, AudioDevice, Binning, CaptureOutput, DeviceOption, DeviceSelection,
+                DevicesVM, FxChain, FxUnit, InputLevel, Latency, PeaksData, PlayError, Window,
+            },
+            tuner::{SpectrogramGrid, TriggerState, TunerEV},
             Activity, RedSiren,
         };
 
         let mut gen = TypeGen::new();
         gen.register_type::<InstrumentEV>()?;
+        gen.register_type::<NodeEvent>()?;
+        gen.register_type::<EnvelopeParams>()?;
+        gen.register_type::<MidiMessage>()?;
         gen.register_type::<IntroEV>()?;
         gen.register_type::<TunerEV>()?;
         gen.register_type::<PlaybackEV>()?;
         gen.register_type::<TriggerState>()?;
+        gen.register_type::<SpectrogramGrid>()?;
         gen.register_type_with_samples(vec![
             CaptureOutput::CaptureFFT(vec![(0.0, 0.0)]),
             CaptureOutput::CaptureData(vec![0.0]),
@@ -53,6 +79,21 @@ fn main() -> anyhow::Result<()> {
                     .map(|f| (f, (0..64).map(|i| i as f32 / 1.0).collect::<Vec<_>>()))
                     .collect::<Vec<_>>(),
             ),
+            CaptureOutput::CapturePeaks(PeaksData {
+                pitch: Some((220.0, 0.5)),
+                peaks: vec![(220.0, 1.0, 0.5)],
+            }),
+            CaptureOutput::CaptureRecording(vec![0, 1, 2, 3]),
+            CaptureOutput::CaptureNoiseFloor(vec![(1, 0.001), (2, 0.0001)]),
+            CaptureOutput::CaptureInputLevel(InputLevel {
+                peak: 1.0,
+                rms: 0.5,
+                clips: 1,
+            }),
+            CaptureOutput::CaptureLatency(Some(Latency {
+                samples: 4800,
+                millis: 100.0,
+            })),
         ])?;
 
         gen.register_type::<Activity>()?;
@@ -61,7 +102,18 @@ fn main() -> anyhow::Result<()> {
         gen.register_type::<Rect>()?;
         gen.register_type::<Config>()?;
         gen.register_type::<Layout>()?;
+        gen.register_type::<Voice>()?;
         gen.register_type::<Node>()?;
+        gen.register_type::<FxUnit>()?;
+        gen.register_type::<FxChain>()?;
+        gen.register_type::<Window>()?;
+        gen.register_type::<Binning>()?;
+        gen.register_type::<AnalysisConfig>()?;
+        gen.register_type::<PlayError>()?;
+        gen.register_type::<AudioDevice>()?;
+        gen.register_type::<DeviceSelection>()?;
+        gen.register_type::<DeviceOption>()?;
+        gen.register_type::<DevicesVM>()?;
 
         gen.register_app::<RedSiren>()?;
 
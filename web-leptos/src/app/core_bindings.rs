@@ -8,7 +8,8 @@ use leptos::*;
 
 use app_core::animate::{AnimateOperation, AnimateOperationOutput};
 use app_core::{
-    navigate::NavigateOperation, Activity, Effect, Event, RedSiren, RedSirenCapabilities, ViewModel,
+    midi::MidiOperation, navigate::NavigateOperation, share::ShareOperation, Activity, Effect,
+    Event, RedSiren, RedSirenCapabilities, ViewModel,
 };
 
 use super::playback;
@@ -159,5 +160,45 @@ pub fn process_effect(
             }
             AnimateOperation::Stop => animate_cb(None),
         },
+        #[allow(unused_variables)]
+        Effect::Midi(req) => match req.operation {
+            MidiOperation::Export(smf) => {
+                #[cfg(feature = "browser")]
+                if let Err(e) = download("red-siren.mid", "audio/midi", smf.as_slice()) {
+                    log::error!("midi not saved: {e:?}");
+                }
+            }
+            op => log::trace!("midi request: {op:?}"),
+        },
+        #[allow(unused_variables)]
+        Effect::Share(req) => match req.operation {
+            ShareOperation::Recording(wav) => {
+                #[cfg(feature = "browser")]
+                if let Err(e) = download("red-siren.wav", "audio/wav", wav.as_slice()) {
+                    log::error!("recording not saved: {e:?}");
+                }
+            }
+        },
     };
 }
+
+/// Offers `bytes` as a file download
+#[cfg(feature = "browser")]
+fn download(name: &str, mime: &str, bytes: &[u8]) -> Result<(), wasm_bindgen::JsValue> {
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let anchor = document()
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url)
+}
@@ -1,15 +1,17 @@
-use leptos::*;
+use leptos::{ev::PointerEvent, *};
 use leptos_meta::Title;
 
 use app_core::instrument;
 pub use button::ButtonComponent;
 use leptos_use::{use_raf_fn_with_options, utils::Pausable, UseRafFnOptions};
+pub use midi_file::MidiFileComponent;
 pub use string::StringComponent;
 pub use track::TrackComponent;
 
 use super::menu::MenuComponent;
 
 mod button;
+mod midi_file;
 mod string;
 mod track;
 
@@ -25,6 +27,8 @@ pub fn InstrumentComponent(
     let outbound_data = Signal::derive(move || vm().data_out);
 
     let playing = Signal::derive(move || vm().playing);
+    let midi_loaded = Signal::derive(move || vm().midi_loaded);
+    let midi_playing = Signal::derive(move || vm().midi_playing);
 
     let (ts, set_ts) = create_signal(0.0);
     let Pausable {
@@ -41,7 +45,7 @@ pub fn InstrumentComponent(
     create_effect(move |prev| {
       let now = ts();
       if prev.map_or(true, |p| now - p >= 42.0 ) {
-        ev(instrument::InstrumentEV::RequestSnoops);
+        ev(instrument::InstrumentEV::RequestSnoops(now));
         now
       }
       else {
@@ -62,6 +66,23 @@ pub fn InstrumentComponent(
 
     let menu_position = Signal::derive(move || vm().layout.menu_position);
 
+    let activate = Callback::new(move |e: PointerEvent| {
+        e.prevent_default();
+        let c = (e.client_x() as f64, e.client_y() as f64);
+        ev.set(instrument::InstrumentEV::ActivationXY(c, e.pointer_id(), e.pressure()))
+    });
+
+    let deactivate = Callback::new(move |e: PointerEvent| {
+        e.prevent_default();
+        ev.set(instrument::InstrumentEV::DeactivationXY(e.pointer_id()))
+    });
+
+    let active_move = Callback::new(move |e: PointerEvent| {
+        e.prevent_default();
+        let c = (e.client_x() as f64, e.client_y() as f64);
+        ev.set(instrument::InstrumentEV::MovementXY(c, e.pointer_id(), e.pressure()))
+    });
+
     let buttons = move || {
         vm().layout
             .buttons
@@ -96,9 +117,14 @@ pub fn InstrumentComponent(
             }
           ).collect_view()}
         </svg>
-        <div class="w-full h-full relative">
+        <div class="w-full h-full relative"
+          on:pointerdown=activate
+          on:pointerup=deactivate
+          on:pointercancel=deactivate
+          on:pointermove=active_move>
           {buttons}
         </div>
+        <MidiFileComponent loaded=midi_loaded playing=midi_playing ev=ev />
         <MenuComponent position={menu_position} playing=playing />
       </div>
     }
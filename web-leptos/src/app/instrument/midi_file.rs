@@ -0,0 +1,58 @@
+use leptos::*;
+
+use app_core::{instrument::InstrumentEV, play::Transport};
+
+/// Loads a MIDI file to play on the nodes and starts or stops it
+#[component]
+#[allow(unused_variables)]
+pub fn MidiFileComponent(
+    #[prop(into)] loaded: Signal<bool>,
+    #[prop(into)] playing: Signal<bool>,
+    ev: SignalSetter<InstrumentEV>,
+) -> impl IntoView {
+    let load = move |e: ev::Event| {
+        #[cfg(feature = "browser")]
+        {
+            use wasm_bindgen::JsCast;
+
+            let file = e
+                .target()
+                .and_then(|t| t.dyn_into::<web_sys::HtmlInputElement>().ok())
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0));
+
+            if let Some(file) = file {
+                spawn_local(async move {
+                    match wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await {
+                        Ok(buffer) => ev.set(InstrumentEV::LoadMidi(
+                            js_sys::Uint8Array::new(&buffer).to_vec(),
+                        )),
+                        Err(e) => log::error!("read midi file: {e:?}"),
+                    }
+                });
+            }
+        }
+    };
+
+    let transport = move |_| {
+        ev.set(InstrumentEV::MidiTransport(if playing() {
+            Transport::Stop
+        } else {
+            Transport::Play
+        }))
+    };
+
+    let label = move || if playing() { "Stop MIDI" } else { "Play MIDI" };
+    let btn_class = "rounded-2xl bg-red dark:bg-black text-black dark:text-red text-xl hover:text-gray dark:hover:text-cinnabar";
+
+    view! {
+      <div class="absolute bottom-0 left-0 flex gap-2 p-2">
+        <input type="file" accept=".mid,.midi,audio/midi" on:change=load/>
+        <Show when=loaded>
+            <button class=btn_class on:click=transport>
+                {label}
+            </button>
+        </Show>
+      </div>
+    }
+}
@@ -6,14 +6,35 @@ import {
   PlayOperationVariantInstallAU,
   PlayOperationVariantResume,
   PlayOperationVariantSuspend,
+  PlayErrorVariantPermissionDenied,
+  PlayErrorVariantDeviceUnavailable,
+  PlayErrorVariantProtocolMismatch,
+  PlayError,
+  PlayOperationVariantQueryInputDevices,
+  PlayOperationVariantQueryOutputDevices,
+  PlayOperationVariantSelectDevices,
+  PlayOperationOutputVariantDevices,
+  AudioDevice,
+  DeviceSelection,
 } from "typegen/types/au_types";
 import { RedSirenNode } from "./node";
 import { BincodeDeserializer, BincodeSerializer } from "typegen/bincode/mod";
 
+function failure(error: PlayError): PlayOperationOutputVariantFailure {
+  return new PlayOperationOutputVariantFailure(error);
+}
+
+function deviceUnavailable(e: unknown): PlayOperationOutputVariantFailure {
+  return failure(new PlayErrorVariantDeviceUnavailable(String(e)));
+}
+
+type SinkContext = AudioContext & { setSinkId?: (id: string) => Promise<void> };
+
 export class PlaybackBridge {
   private ctx?: AudioContext;
   private redSirenNode?: RedSirenNode;
   private inputNode?: MediaStreamAudioSourceNode;
+  private selection = new DeviceSelection(null, null);
 
   public on_capture?: (data: Uint8Array) => void;
 
@@ -26,12 +47,42 @@ export class PlaybackBridge {
     const op = PlayOperation.deserialize(new BincodeDeserializer(bytes));
     const ser = new BincodeSerializer();
 
+    switch (op.constructor) {
+      case PlayOperationVariantQueryInputDevices:
+      case PlayOperationVariantQueryOutputDevices: {
+        try {
+          const kind =
+            op instanceof PlayOperationVariantQueryInputDevices
+              ? "audioinput"
+              : "audiooutput";
+          new PlayOperationOutputVariantDevices(await this.devices(kind)).serialize(ser);
+        } catch (e) {
+          console.error(e);
+          deviceUnavailable(e).serialize(ser);
+        }
+        return ser.getBytes();
+      }
+      case PlayOperationVariantSelectDevices: {
+        try {
+          await this.select((op as PlayOperationVariantSelectDevices).value);
+          new PlayOperationOutputVariantSuccess().serialize(ser);
+        } catch (e) {
+          console.error(e);
+          deviceUnavailable(e).serialize(ser);
+        }
+        return ser.getBytes();
+      }
+    }
+
     if (!this.ctx || !this.redSirenNode || !this.inputNode) {
       switch (op.constructor) {
         case PlayOperationVariantPermissions: {
           try {
             const media = navigator.mediaDevices;
-            const stream = await media.getUserMedia({ audio: true });
+            const input = this.selection.input;
+            const stream = await media.getUserMedia({
+              audio: input === null ? true : { deviceId: { exact: input } },
+            });
             const ctx = new AudioContext();
             const inputNode = new MediaStreamAudioSourceNode(ctx, {
               mediaStream: stream,
@@ -43,12 +94,20 @@ export class PlaybackBridge {
             new PlayOperationOutputVariantSuccess().serialize(ser);
           } catch (e) {
             console.error(e);
-            new PlayOperationOutputVariantFailure().serialize(ser);
+            if (e instanceof DOMException && e.name === "NotAllowedError") {
+              failure(new PlayErrorVariantPermissionDenied()).serialize(ser);
+            } else {
+              deviceUnavailable(e).serialize(ser);
+            }
           }
           return ser.getBytes();
         }
         case PlayOperationVariantInstallAU: {
           try {
+            const ctx: SinkContext = this.ctx!;
+            if (this.selection.output !== null && ctx.setSinkId) {
+              await ctx.setSinkId(this.selection.output);
+            }
             await RedSirenNode.addModule(this.ctx!);
             this.redSirenNode = new RedSirenNode(this.ctx!);
             console.log("init worklet");
@@ -64,7 +123,7 @@ export class PlaybackBridge {
             new PlayOperationOutputVariantSuccess().serialize(ser);
           } catch (e) {
             console.error(e);
-            new PlayOperationOutputVariantFailure().serialize(ser);
+            deviceUnavailable(e).serialize(ser);
           }
           return ser.getBytes();
         }
@@ -83,7 +142,7 @@ export class PlaybackBridge {
             new PlayOperationOutputVariantSuccess().serialize(ser);
           } catch (e) {
             console.error(e);
-            new PlayOperationOutputVariantFailure().serialize(ser);
+            deviceUnavailable(e).serialize(ser);
           }
           return ser.getBytes();
         }
@@ -94,7 +153,7 @@ export class PlaybackBridge {
             new PlayOperationOutputVariantSuccess().serialize(ser);
           } catch (e) {
             console.error(e);
-            new PlayOperationOutputVariantFailure().serialize(ser);
+            deviceUnavailable(e).serialize(ser);
           }
           return ser.getBytes();
         }
@@ -107,11 +166,61 @@ export class PlaybackBridge {
             });
           } catch (e) {
             console.error(e);
-            new PlayOperationOutputVariantFailure().serialize(ser);
+            failure(new PlayErrorVariantProtocolMismatch(String(e))).serialize(ser);
             return ser.getBytes();
           }
         }
       }
     }
   }
+
+  private async devices(kind: MediaDeviceKind): Promise<AudioDevice[]> {
+    const devices = await navigator.mediaDevices.enumerateDevices();
+
+    return devices
+      .filter((device) => device.kind === kind)
+      .map((device) => {
+        // only inputs report capabilities, outputs play what the context renders
+        const caps: MediaTrackCapabilities =
+          "getCapabilities" in device
+            ? (device as InputDeviceInfo).getCapabilities()
+            : {};
+        const channels =
+          caps.channelCount?.max ?? this.ctx?.destination.maxChannelCount ?? 2;
+        const rates = caps.sampleRate
+          ? [caps.sampleRate.min, caps.sampleRate.max]
+          : [this.ctx?.sampleRate];
+
+        return new AudioDevice(
+          device.deviceId,
+          device.label,
+          BigInt(channels),
+          [...new Set(rates.filter((rate): rate is number => rate !== undefined))]
+        );
+      });
+  }
+
+  private async select(selection: DeviceSelection) {
+    const devices = await navigator.mediaDevices.enumerateDevices();
+    const listed = (id: string | null, kind: MediaDeviceKind) =>
+      id === null ||
+      devices.some((device) => device.kind === kind && device.deviceId === id);
+
+    if (!listed(selection.input, "audioinput")) {
+      throw new Error(`no device ${selection.input}`);
+    }
+    if (!listed(selection.output, "audiooutput")) {
+      throw new Error(`no device ${selection.output}`);
+    }
+
+    this.selection = selection;
+
+    // the next permissions request opens streams on the selection
+    if (this.ctx) {
+      await this.ctx.close();
+      this.ctx = undefined;
+      this.redSirenNode = undefined;
+      this.inputNode = undefined;
+    }
+  }
 }
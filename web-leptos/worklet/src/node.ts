@@ -9,7 +9,10 @@ export class RedSirenNode extends AudioWorkletNode {
   private initPromiseReject: ((reason: any) => void) | null = null;
 
   constructor(ctx: AudioContext) {
-    super(ctx, "red-siren");
+    super(ctx, "red-siren", {
+      outputChannelCount: [Math.max(2, ctx.destination.maxChannelCount)],
+    });
+    ctx.destination.channelCount = Math.max(2, ctx.destination.maxChannelCount);
   }
 
   public init() {
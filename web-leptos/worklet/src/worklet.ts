@@ -4,6 +4,8 @@ import { initSync, au_log_init } from "aucore/aucore";
 import {
   ViewModel,
   PlayOperationVariantInput,
+  PlayOperationVariantSampleRate,
+  PlayOperationVariantOutputChannels,
 } from "typegen/types/au_types";
 import { update, update_plain } from "./core";
 
@@ -15,10 +17,13 @@ export class RedSirenWorklet extends AudioWorkletProcessor {
   private fillBuffer = true;
   private evs: Uint8Array[] = []
   private evs_p?: Promise<void>;
+  private channels: number;
 
-  constructor() {
+  constructor(options?: AudioWorkletNodeOptions) {
     super();
 
+    this.channels = options?.outputChannelCount?.[0] ?? 2;
+
     this.port.onmessage = this.onMessage.bind(this);
   }
 
@@ -47,6 +52,18 @@ export class RedSirenWorklet extends AudioWorkletProcessor {
           this.initOutput = initSync(msg.data.wasmBytes);
           console.info("wasm-ready");
           au_log_init();
+          update(
+            new PlayOperationVariantSampleRate(sampleRate),
+            this.onRender,
+            this.onResolve,
+            this.onCapture
+          );
+          update(
+            new PlayOperationVariantOutputChannels(this.channels),
+            this.onRender,
+            this.onResolve,
+            this.onCapture
+          );
           this.port.postMessage({
             type: "wasm-ready",
           });